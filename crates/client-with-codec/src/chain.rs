@@ -0,0 +1,43 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use datagram::{DatagramCodec, DatagramDecoder, DatagramEncoder};
+use std::io;
+
+/// Stacks two codecs into one, so that an extra layer such as compression can be
+/// added without writing a combined codec by hand.
+///
+/// `lower` is the layer closest to the wire. `encode` runs `upper` first and then
+/// `lower`, and `decode` runs them in the opposite order. Chains can be nested to
+/// stack more than two layers.
+///
+/// A datagram that `lower` decodes to nothing, like a handshake datagram of the
+/// datagram-connections layer, is not passed on to `upper`.
+pub struct ChainedCodec<L: DatagramCodec, U: DatagramCodec> {
+    pub lower: L,
+    pub upper: U,
+}
+
+impl<L: DatagramCodec, U: DatagramCodec> ChainedCodec<L, U> {
+    pub fn new(lower: L, upper: U) -> Self {
+        Self { lower, upper }
+    }
+}
+
+impl<L: DatagramCodec, U: DatagramCodec> DatagramEncoder for ChainedCodec<L, U> {
+    fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        let encoded = self.upper.encode(buf)?;
+        self.lower.encode(&encoded)
+    }
+}
+
+impl<L: DatagramCodec, U: DatagramCodec> DatagramDecoder for ChainedCodec<L, U> {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        let decoded = self.lower.decode(buf)?;
+        if decoded.is_empty() {
+            return Ok(decoded);
+        }
+        self.upper.decode(&decoded)
+    }
+}
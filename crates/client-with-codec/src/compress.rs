@@ -0,0 +1,92 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use datagram::{DatagramDecoder, DatagramEncoder};
+use std::io;
+use std::io::ErrorKind;
+
+/// Default for [`CompressingCodec::threshold`].
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 128;
+
+const UNCOMPRESSED: u8 = 0x00;
+const COMPRESSED: u8 = 0x01;
+
+/// Compresses large payloads with [LZ4](https://en.wikipedia.org/wiki/LZ4_(compression_algorithm)).
+///
+/// Every datagram starts with a flag octet telling whether the rest is compressed.
+/// Payloads shorter than `threshold`, or that don't get smaller, are sent as they are.
+///
+/// To use it in a client, stack it on top of the connection with
+/// `ChainedCodec::new(connection.clone(), CompressingCodec::default())` and pass the
+/// chain and the [`SharedConnection`](crate::SharedConnection) to
+/// [`ClientWithCodec::with_codec`](crate::ClientWithCodec::with_codec).
+pub struct CompressingCodec {
+    pub threshold: usize,
+    /// Compressed datagrams that claim to expand to more octets than this are rejected,
+    /// so a small datagram can't make the receiver allocate a huge buffer.
+    pub max_decompressed_size: usize,
+}
+
+impl CompressingCodec {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            max_decompressed_size: 64 * 1024,
+        }
+    }
+}
+
+impl Default for CompressingCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_COMPRESSION_THRESHOLD)
+    }
+}
+
+impl DatagramEncoder for CompressingCodec {
+    fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        if buf.len() >= self.threshold {
+            let compressed = lz4_flex::compress_prepend_size(buf);
+            if compressed.len() < buf.len() {
+                return Ok([&[COMPRESSED], compressed.as_slice()].concat());
+            }
+        }
+        Ok([&[UNCOMPRESSED], buf].concat())
+    }
+}
+
+impl DatagramDecoder for CompressingCodec {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        match buf.split_first() {
+            Some((&UNCOMPRESSED, payload)) => Ok(payload.to_vec()),
+            Some((&COMPRESSED, payload)) => {
+                let size_octets: [u8; 4] = payload
+                    .get(..4)
+                    .and_then(|octets| octets.try_into().ok())
+                    .ok_or_else(|| {
+                        io::Error::new(ErrorKind::UnexpectedEof, "compressed size is truncated")
+                    })?;
+                let size = u32::from_le_bytes(size_octets) as usize;
+                if size > self.max_decompressed_size {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "decompressed size {size} is larger than the maximum of {}",
+                            self.max_decompressed_size
+                        ),
+                    ));
+                }
+                lz4_flex::decompress_size_prepended(payload)
+                    .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+            }
+            Some((flag, _)) => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown compression flag {flag}"),
+            )),
+            None => Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "datagram is missing the compression flag",
+            )),
+        }
+    }
+}
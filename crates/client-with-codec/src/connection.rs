@@ -0,0 +1,62 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use datagram::{DatagramDecoder, DatagramEncoder};
+use datagram_connections::prelude::Client as ConnectionsClient;
+use datagram_connections::{ClientEvent, ClientPhase};
+use secure_random::SecureRandom;
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+/// A datagram-connections client that is shared between a codec and its owner.
+///
+/// One clone is boxed as the codec, or as a layer in a
+/// [`ChainedCodec`](crate::ChainedCodec), and another is kept to read the connection
+/// phase and events, which are not reachable through a `Box<dyn DatagramCodec>`.
+#[derive(Clone)]
+pub struct SharedConnection(Rc<RefCell<ConnectionsClient>>);
+
+impl SharedConnection {
+    pub fn new(random: Box<dyn SecureRandom>) -> Self {
+        Self(Rc::new(RefCell::new(ConnectionsClient::new(random))))
+    }
+
+    pub fn phase(&self) -> ClientPhase {
+        *self.0.borrow().phase()
+    }
+
+    /// Returns the connection events, such as [`ClientEvent::Connected`], since the last call.
+    pub fn poll_events(&self) -> Vec<ClientEvent> {
+        self.0.borrow_mut().poll_events()
+    }
+
+    /// Ends the connection because the host has been silent, see [`ConnectionsClient::time_out`].
+    pub fn time_out(&self) {
+        self.0.borrow_mut().time_out();
+    }
+
+    /// Starts a disconnect, see [`ConnectionsClient::disconnect`], and returns the datagram
+    /// with the disconnect command.
+    ///
+    /// The datagram is encoded by the connection alone, so that codecs stacked on top of it,
+    /// like compression, don't add their framing after the command.
+    pub fn disconnect_datagram(&self) -> io::Result<Vec<u8>> {
+        let mut connection = self.0.borrow_mut();
+        connection.disconnect();
+        connection.encode(&[])
+    }
+}
+
+impl DatagramEncoder for SharedConnection {
+    fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        self.0.borrow_mut().encode(buf)
+    }
+}
+
+impl DatagramDecoder for SharedConnection {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        DatagramDecoder::decode(&mut *self.0.borrow_mut(), buf)
+    }
+}
@@ -0,0 +1,57 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use nimble_rust::ClientError;
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+/// An encoded datagram was larger than the client allows to send.
+///
+/// [`ClientError`] belongs to nimble-rust and has no variant for it, so it is returned
+/// inside [`ClientError::IoError`] with [`io::ErrorKind::InvalidInput`].
+/// Use [`DatagramTooLarge::from_client_error`] to get it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramTooLarge {
+    pub size: usize,
+    pub max: usize,
+}
+
+impl DatagramTooLarge {
+    /// Returns the [`DatagramTooLarge`] that `err` carries, if it is one.
+    pub fn from_client_error(err: &ClientError) -> Option<&Self> {
+        let ClientError::IoError(io_err) = err else {
+            return None;
+        };
+        io_err.get_ref()?.downcast_ref::<Self>()
+    }
+}
+
+impl Display for DatagramTooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "datagram of {} octets is larger than the maximum of {}",
+            self.size, self.max
+        )
+    }
+}
+
+impl error::Error for DatagramTooLarge {}
+
+impl From<DatagramTooLarge> for io::Error {
+    fn from(err: DatagramTooLarge) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// Fails with [`DatagramTooLarge`] if `datagram` is longer than `max` octets.
+pub(crate) fn check_datagram_size(datagram: &[u8], max: usize) -> Result<(), DatagramTooLarge> {
+    if datagram.len() > max {
+        return Err(DatagramTooLarge {
+            size: datagram.len(),
+            max,
+        });
+    }
+    Ok(())
+}
@@ -2,7 +2,12 @@
  * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
+use crate::error::check_datagram_size;
+use crate::{
+    ReceiveBatch, SharedConnection, DEFAULT_MAX_DATAGRAMS_PER_UPDATE, DEFAULT_MAX_DATAGRAM_SIZE,
+};
 use datagram::{DatagramCodec, DatagramCommunicator};
+use datagram_connections::ClientEvent;
 use flood_rs::{Deserialize, Serialize};
 use hexify::format_hex;
 use log::{error, info, warn};
@@ -20,6 +25,7 @@ pub struct ExampleClientWithLayer<
     pub communicator: Box<dyn DatagramCommunicator>,
     pub codec: Box<dyn DatagramCodec>,
     pub connection_layer_codec: Box<dyn DatagramCodec>,
+    connection: SharedConnection,
 }
 
 impl<
@@ -27,20 +33,18 @@ impl<
         StepT: Clone + Deserialize + Serialize + Debug + Display + Eq,
     > ExampleClientWithLayer<GameT, StepT>
 {
-    pub fn new(url: &str) -> Self {
+    pub fn new(url: &str) -> Result<Self, ClientError> {
         let now = Millis::new(0);
         let client = Client::<GameT, StepT>::new(now);
-        let udp_client = UdpClient::new(url).unwrap();
+        let udp_client = UdpClient::new(url).map_err(ClientError::IoError)?;
         let communicator: Box<dyn DatagramCommunicator> = Box::new(udp_client);
         let random2 = GetRandom;
         let random2_box = Box::new(random2);
-        let datagram_connections_layer = datagram_connections::prelude::Client::new(random2_box);
+        let connection = SharedConnection::new(random2_box);
 
         let connection_layer = connection_layer::prelude::ConnectionLayerClientCodec::new(0);
         let connection_layer_codec: Box<dyn DatagramCodec> = Box::new(connection_layer);
 
-        let datagram_connections_codec_box: Box<dyn DatagramCodec> =
-            Box::new(datagram_connections_layer);
         //let joining_player = JoinPlayerRequest { local_index: 32 };
         /*
                 let join_game_request = JoinGameRequest {
@@ -55,16 +59,22 @@ impl<
         // client.debug_set_tick_id(0x8BADF00D);
         // thread::sleep(Duration::from_millis(16));
 
-        Self {
+        Ok(Self {
             client,
             communicator,
-            codec: datagram_connections_codec_box,
+            codec: Box::new(connection.clone()),
             connection_layer_codec,
-        }
+            connection,
+        })
+    }
+
+    /// Returns the connection events, such as [`ClientEvent::Connected`], since the last call.
+    pub fn poll_events(&mut self) -> Vec<ClientEvent> {
+        self.connection.poll_events()
     }
 
     pub fn update(&mut self, now: Millis) -> Result<(), ClientError> {
-        let mut buf = [1u8; 1200];
+        let mut buf = [1u8; DEFAULT_MAX_DATAGRAM_SIZE];
         let datagrams_to_send = self.client.send(now)?;
         for datagram_to_send in datagrams_to_send {
             info!(
@@ -80,19 +90,24 @@ impl<
                 .codec
                 .encode(processed_with_layer.as_slice())
                 .map_err(ClientError::IoError)?;
+            check_datagram_size(&processed_with_udp_connections, DEFAULT_MAX_DATAGRAM_SIZE)
+                .map_err(|err| ClientError::IoError(err.into()))?;
             self.communicator
                 .send(processed_with_udp_connections.as_slice())
                 .map_err(ClientError::IoError)?;
         }
-        if let Ok(size) = self.communicator.receive(&mut buf) {
-            let received_buf = &buf[0..size];
+        let received_datagrams = self
+            .communicator
+            .try_receive_batch(&mut buf, DEFAULT_MAX_DATAGRAMS_PER_UPDATE)
+            .map_err(ClientError::IoError)?;
+        for received_buf in received_datagrams {
             info!(
                 "received datagram of size: {} payload: {}",
-                size,
-                format_hex(received_buf)
+                received_buf.len(),
+                format_hex(&received_buf)
             );
 
-            match self.codec.decode(received_buf) {
+            match self.codec.decode(&received_buf) {
                 Ok(datagram_for_client) => {
                     if !datagram_for_client.is_empty() {
                         info!(
@@ -2,10 +2,26 @@
  * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
+mod chain;
+#[cfg(feature = "compression")]
+mod compress;
+mod connection;
+mod error;
 pub mod layer;
+mod metrics;
+mod receive;
 pub use app_version::{Version, VersionProvider};
+pub use chain::ChainedCodec;
+#[cfg(feature = "compression")]
+pub use compress::{CompressingCodec, DEFAULT_COMPRESSION_THRESHOLD};
+pub use connection::SharedConnection;
+pub use error::DatagramTooLarge;
+pub use metrics::ClientMetrics;
+pub use receive::ReceiveBatch;
 
 use datagram::{DatagramCodec, DatagramCommunicator};
+use datagram_connections::{ClientEvent, ClientPhase};
+use error::check_datagram_size;
 use flood_rs::{Deserialize, Serialize};
 use hexify::format_hex;
 use log::{error, info, warn};
@@ -14,8 +30,24 @@ pub use nimble_rust::*;
 
 use secure_random::GetRandom;
 use std::fmt::{Debug, Display};
+use std::time::Duration;
 use udp_client::UdpClient;
 
+/// Default for [`ClientWithCodec::max_datagrams_per_update`].
+pub const DEFAULT_MAX_DATAGRAMS_PER_UPDATE: usize = 64;
+
+/// Default for [`ClientWithCodec::set_send_interval`], which sends on every update.
+///
+/// Games that update faster than they want to send opt in to pacing with a longer interval.
+pub const DEFAULT_SEND_INTERVAL: Duration = Duration::ZERO;
+
+/// Default for [`ClientWithCodec::max_datagram_size`].
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1200;
+
+/// Default for [`ClientWithCodec::connection_timeout`], the same as the idle timeout of the
+/// connection-layer host.
+pub const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct ClientWithCodec<
     StateT: GameCallbacks<StepT> + Debug,
     StepT: Clone + Deserialize + Serialize + Debug + Display + Eq,
@@ -23,6 +55,26 @@ pub struct ClientWithCodec<
     pub client: Client<StateT, StepT>,
     pub communicator: Box<dyn DatagramCommunicator>,
     pub codec: Box<dyn DatagramCodec>,
+    connection: SharedConnection,
+    /// Maximum number of received datagrams processed in one [`ClientWithCodec::update`].
+    ///
+    /// The rest stay queued in the communicator until the next update. If the host
+    /// sends faster than this rate, the queue grows until the transport starts dropping
+    /// datagrams, instead of `update` never returning to the game loop.
+    pub max_datagrams_per_update: usize,
+    /// Largest encoded datagram, in octets, that [`ClientWithCodec::update`] hands to the
+    /// communicator. A larger one fails the update with [`DatagramTooLarge`].
+    ///
+    /// It also sizes the receive buffer, so received datagrams are limited the same way.
+    pub max_datagram_size: usize,
+    /// An established connection ends with
+    /// [`DisconnectReason::TimedOut`](datagram_connections::DisconnectReason::TimedOut) if no
+    /// datagram from the host could be decoded for longer than this.
+    pub connection_timeout: Duration,
+    send_interval: Duration,
+    last_send: Option<Millis>,
+    last_receive: Option<Millis>,
+    metrics: ClientMetrics,
 }
 
 impl<
@@ -30,23 +82,46 @@ impl<
         StepT: Clone + Deserialize + Serialize + Debug + Display + Eq + PartialEq,
     > ClientWithCodec<StateT, StepT>
 {
-    pub fn new(url: &str) -> Self {
-        let now = Millis::new(0);
-        let client = Client::<StateT, StepT>::new(now);
-        let udp_client = UdpClient::new(url).unwrap();
-        let communicator: Box<dyn DatagramCommunicator> = Box::new(udp_client);
+    pub fn new(url: &str) -> Result<Self, ClientError> {
+        let udp_client = UdpClient::new(url).map_err(ClientError::IoError)?;
+        Ok(Self::with_communicator(Box::new(udp_client)))
+    }
+
+    /// Creates a client that sends and receives through `communicator` instead of a UDP socket.
+    pub fn with_communicator(communicator: Box<dyn DatagramCommunicator>) -> Self {
         let random2 = GetRandom;
         let random2_box = Box::new(random2);
-        let datagram_connections_layer_client =
-            datagram_connections::prelude::Client::new(random2_box);
+        let connection = SharedConnection::new(random2_box);
+        let codec = Box::new(connection.clone());
+        Self::with_codec(communicator, connection, codec)
+    }
 
-        let datagram_connections_codec_box: Box<dyn DatagramCodec> =
-            Box::new(datagram_connections_layer_client);
+    /// Creates a client that encodes and decodes with `codec` instead of only the
+    /// datagram-connections layer.
+    ///
+    /// `codec` must contain a clone of `connection`, usually as the lower layer of a
+    /// [`ChainedCodec`], so that the handshake runs and [`ClientWithCodec::poll_events`]
+    /// and [`ClientWithCodec::phase`] report it.
+    pub fn with_codec(
+        communicator: Box<dyn DatagramCommunicator>,
+        connection: SharedConnection,
+        codec: Box<dyn DatagramCodec>,
+    ) -> Self {
+        let now = Millis::new(0);
+        let client = Client::<StateT, StepT>::new(now);
 
         Self {
             client,
             communicator,
-            codec: datagram_connections_codec_box,
+            codec,
+            connection,
+            max_datagrams_per_update: DEFAULT_MAX_DATAGRAMS_PER_UPDATE,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            send_interval: DEFAULT_SEND_INTERVAL,
+            last_send: None,
+            last_receive: None,
+            metrics: ClientMetrics::default(),
         }
     }
 
@@ -54,8 +129,75 @@ impl<
         self.client.game()
     }
 
-    pub fn update(&mut self, now: Millis) -> Result<(), ClientError> {
-        let mut buf = [1u8; 1200];
+    pub fn send_interval(&self) -> Duration {
+        self.send_interval
+    }
+
+    /// Sets the minimum time between two sends in [`ClientWithCodec::update`].
+    ///
+    /// Updates that come sooner only receive, so a game that updates at a high rate
+    /// doesn't send a datagram every frame. [`Duration::ZERO`] sends on every update.
+    pub fn set_send_interval(&mut self, interval: Duration) {
+        self.send_interval = interval;
+    }
+
+    fn is_time_to_send(&self, now: Millis) -> bool {
+        let Some(last_send) = self.last_send else {
+            return true;
+        };
+        let elapsed = now
+            .absolute_milliseconds()
+            .saturating_sub(last_send.absolute_milliseconds());
+        u128::from(elapsed) >= self.send_interval.as_millis()
+    }
+
+    fn is_timed_out(&self, now: Millis) -> bool {
+        let Some(last_receive) = self.last_receive else {
+            return false;
+        };
+        let elapsed = now
+            .absolute_milliseconds()
+            .saturating_sub(last_receive.absolute_milliseconds());
+        u128::from(elapsed) > self.connection_timeout.as_millis()
+    }
+
+    pub fn metrics(&self) -> &ClientMetrics {
+        &self.metrics
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.metrics.reset();
+    }
+
+    /// Returns the connection events, such as [`ClientEvent::Connected`], since the last call.
+    pub fn poll_events(&mut self) -> Vec<ClientEvent> {
+        self.connection.poll_events()
+    }
+
+    pub fn phase(&self) -> ClientPhase {
+        self.connection.phase()
+    }
+
+    /// Tells the host that the client is leaving, so it doesn't have to time the client out.
+    ///
+    /// Sends one disconnect command if the client is connected and does nothing otherwise.
+    /// Called by `Drop`, so the host is notified best-effort even if this is never called.
+    pub fn close(&mut self) -> Result<(), ClientError> {
+        if !matches!(self.connection.phase(), ClientPhase::Connected(_)) {
+            return Ok(());
+        }
+        let disconnect = self
+            .connection
+            .disconnect_datagram()
+            .map_err(ClientError::IoError)?;
+        self.communicator
+            .send(disconnect.as_slice())
+            .map_err(ClientError::IoError)?;
+        self.metrics.on_sent(disconnect.len());
+        Ok(())
+    }
+
+    fn send(&mut self, now: Millis) -> Result<(), ClientError> {
         let datagrams_to_send = self.client.send(now)?;
         for datagram_to_send in datagrams_to_send {
             info!(
@@ -67,23 +209,42 @@ impl<
                 .codec
                 .encode(datagram_to_send.as_slice())
                 .map_err(ClientError::IoError)?;
+            check_datagram_size(&processed, self.max_datagram_size)
+                .map_err(|err| ClientError::IoError(err.into()))?;
             self.communicator
                 .send(processed.as_slice())
                 .map_err(ClientError::IoError)?;
+            self.metrics.on_sent(processed.len());
         }
-        while let Ok(size) = self.communicator.receive(&mut buf) {
-            if size == 0 {
-                // No more data to process; exit the loop
-                break;
-            }
-            let received_buf = &buf[0..size];
+        Ok(())
+    }
+
+    /// Sends, if [`ClientWithCodec::send_interval`] has passed since the last send, and
+    /// then processes the received datagrams.
+    ///
+    /// Ends the connection if the host has been silent for longer than
+    /// [`ClientWithCodec::connection_timeout`].
+    pub fn update(&mut self, now: Millis) -> Result<(), ClientError> {
+        if self.is_time_to_send(now) {
+            self.last_send = Some(now);
+            self.send(now)?;
+        }
+
+        let mut buf = vec![0u8; self.max_datagram_size];
+        let received_datagrams = self
+            .communicator
+            .try_receive_batch(&mut buf, self.max_datagrams_per_update)
+            .map_err(ClientError::IoError)?;
+        for received_buf in received_datagrams {
+            self.metrics.on_received(received_buf.len());
             info!(
                 "received datagram of size: {} payload: {}",
-                size,
-                format_hex(received_buf)
+                received_buf.len(),
+                format_hex(&received_buf)
             );
-            match self.codec.decode(received_buf) {
+            match self.codec.decode(&received_buf) {
                 Ok(datagram_for_client) => {
+                    self.last_receive = Some(now);
                     if !datagram_for_client.is_empty() {
                         info!(
                             "received datagram to normal client: {}",
@@ -101,6 +262,31 @@ impl<
                 Err(some_error) => error!("error {}", some_error),
             }
         }
+
+        if matches!(
+            self.connection.phase(),
+            ClientPhase::Connected(_) | ClientPhase::Disconnecting(_)
+        ) && self.is_timed_out(now)
+        {
+            warn!("no datagram from the host since {:?}", self.last_receive);
+            self.connection.time_out();
+        }
         Ok(())
     }
 }
+
+impl<
+        StateT: GameCallbacks<StepT> + Debug,
+        StepT: Clone + Deserialize + Serialize + Debug + Display + Eq,
+    > Drop for ClientWithCodec<StateT, StepT>
+{
+    fn drop(&mut self) {
+        // A panic while already unwinding would abort, so skip the notice then.
+        if std::thread::panicking() {
+            return;
+        }
+        if let Err(err) = self.close() {
+            warn!("could not send disconnect on drop: {:?}", err);
+        }
+    }
+}
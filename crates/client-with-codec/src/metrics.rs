@@ -0,0 +1,36 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+/// Datagram and octet counters for a [`ClientWithCodec`](crate::ClientWithCodec).
+///
+/// Counts what goes through the communicator, so the octets include the codec
+/// headers. Received datagrams are counted even if the codec rejects them.
+///
+/// There is no commands per datagram counter. The nimble [`Client`](crate::Client)
+/// hands over each datagram already serialized, so at this layer a datagram is opaque
+/// octets and the commands in it can only be counted by the client that wrote them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClientMetrics {
+    pub datagrams_sent: u64,
+    pub octets_sent: u64,
+    pub datagrams_received: u64,
+    pub octets_received: u64,
+}
+
+impl ClientMetrics {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn on_sent(&mut self, octet_count: usize) {
+        self.datagrams_sent += 1;
+        self.octets_sent += octet_count as u64;
+    }
+
+    pub(crate) fn on_received(&mut self, octet_count: usize) {
+        self.datagrams_received += 1;
+        self.octets_received += octet_count as u64;
+    }
+}
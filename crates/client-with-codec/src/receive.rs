@@ -0,0 +1,53 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use datagram::DatagramReceiver;
+use log::warn;
+use std::io;
+
+/// Receives all datagrams that are available right now, without blocking.
+///
+/// Implemented for every [`DatagramReceiver`], including `dyn DatagramCommunicator`.
+pub trait ReceiveBatch: DatagramReceiver {
+    /// Receives up to `max_count` datagrams, using `buffer` as scratch space.
+    ///
+    /// Both `Ok(0)` and [`io::ErrorKind::WouldBlock`] from the receiver end the batch,
+    /// since they mean that nothing more is queued right now.
+    ///
+    /// [`io::ErrorKind::ConnectionRefused`] is logged and also ends the batch. A UDP socket
+    /// reports an ICMP port unreachable that way, for example when the client starts
+    /// before the host, and the host may well be up by the next batch.
+    ///
+    /// # Errors
+    ///
+    /// Any other receive error is returned if it happens before anything was received.
+    /// An error after the first datagram only ends the batch and is logged, so that a
+    /// stray error doesn't throw away datagrams already received.
+    fn try_receive_batch(
+        &mut self,
+        buffer: &mut [u8],
+        max_count: usize,
+    ) -> io::Result<Vec<Vec<u8>>> {
+        let mut datagrams = Vec::new();
+        while datagrams.len() < max_count {
+            match self.receive(buffer) {
+                Ok(0) => break,
+                Ok(size) => datagrams.push(buffer[..size].to_vec()),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    warn!("receive refused, the host may not be running yet: {err}");
+                    break;
+                }
+                Err(err) if datagrams.is_empty() => return Err(err),
+                Err(err) => {
+                    warn!("receive failed after {} datagrams: {err}", datagrams.len());
+                    break;
+                }
+            }
+        }
+        Ok(datagrams)
+    }
+}
+
+impl<T: DatagramReceiver + ?Sized> ReceiveBatch for T {}
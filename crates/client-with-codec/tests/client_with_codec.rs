@@ -1,9 +1,535 @@
-use nimble_client_with_codec::ClientWithCodec;
-use nimble_rust::{SampleGame, SampleStep};
+use datagram::{DatagramDecoder, DatagramEncoder, DatagramReceiver, DatagramSender};
+use datagram_connections::{ClientEvent, ClientPhase, ConnectionId, DisconnectReason};
+use datagram_test_support::LoopbackCommunicator;
+use monotonic_time_rs::Millis;
+use nimble_client_with_codec::{
+    ChainedCodec, ClientMetrics, ClientWithCodec, DatagramTooLarge, ReceiveBatch, SharedConnection,
+    DEFAULT_MAX_DATAGRAMS_PER_UPDATE,
+};
+use nimble_rust::{ClientError, SampleGame, SampleStep};
+use secure_random::GetRandom;
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
 
 #[test]
 fn test_client_with_codec() {
-    let x = ClientWithCodec::<SampleGame, SampleStep>::new("127.0.0.1:22000");
+    let x = ClientWithCodec::<SampleGame, SampleStep>::new("127.0.0.1:22000").unwrap();
 
     assert!(x.client.game().is_none())
 }
+
+#[test]
+fn unresolvable_host_is_an_error() {
+    let result = ClientWithCodec::<SampleGame, SampleStep>::new("host.invalid:22000");
+
+    assert!(result.is_err())
+}
+
+#[test]
+fn update_stops_after_max_datagrams() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+
+    const DATAGRAM_COUNT: usize = 100;
+    for _ in 0..DATAGRAM_COUNT {
+        host.send(&[0xff]).unwrap();
+    }
+
+    client.update(Millis::new(0)).unwrap();
+    assert_eq!(
+        host.pending_outgoing(),
+        DATAGRAM_COUNT - DEFAULT_MAX_DATAGRAMS_PER_UPDATE
+    );
+
+    client.update(Millis::new(16)).unwrap();
+    assert_eq!(host.pending_outgoing(), 0);
+}
+
+#[test]
+fn oversized_datagram_is_not_sent() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+    client.max_datagram_size = 1;
+
+    let err = client.update(Millis::new(0)).unwrap_err();
+    assert!(
+        matches!(&err, ClientError::IoError(io_err) if io_err.kind() == io::ErrorKind::InvalidInput)
+    );
+    assert!(matches!(
+        DatagramTooLarge::from_client_error(&err),
+        Some(DatagramTooLarge { max: 1, size }) if *size > 1
+    ));
+
+    let mut buf = [0u8; 1200];
+    assert_eq!(host.receive(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn update_sends_on_every_update_by_default() {
+    let (communicator, _host) = LoopbackCommunicator::pair();
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+    assert_eq!(client.send_interval(), Duration::ZERO);
+
+    client.update(Millis::new(0)).unwrap();
+    let sent_in_first_update = client.metrics().datagrams_sent;
+    client.update(Millis::new(0)).unwrap();
+
+    assert!(client.metrics().datagrams_sent > sent_in_first_update);
+}
+
+#[test]
+fn update_sends_once_per_interval() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+    client.set_send_interval(Duration::from_millis(16));
+
+    client.update(Millis::new(0)).unwrap();
+    let sent_in_first_update = client.metrics().datagrams_sent;
+    assert!(sent_in_first_update > 0);
+
+    for now in 1..16 {
+        host.send(&[0xff]).unwrap();
+        client.update(Millis::new(now)).unwrap();
+    }
+    assert_eq!(client.metrics().datagrams_sent, sent_in_first_update);
+    assert_eq!(client.metrics().datagrams_received, 15);
+    assert_eq!(host.pending_outgoing(), 0);
+
+    client.update(Millis::new(16)).unwrap();
+    assert!(client.metrics().datagrams_sent > sent_in_first_update);
+}
+
+#[test]
+fn metrics_count_datagrams_and_octets() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+
+    host.send(&[0xff]).unwrap();
+    host.send(&[0xff, 0xfe]).unwrap();
+    client.update(Millis::new(0)).unwrap();
+
+    let mut buf = [0u8; 1200];
+    let mut datagrams_sent = 0;
+    let mut octets_sent = 0;
+    loop {
+        let size = host.receive(&mut buf).unwrap();
+        if size == 0 {
+            break;
+        }
+        datagrams_sent += 1;
+        octets_sent += size as u64;
+    }
+    assert!(datagrams_sent > 0);
+
+    assert_eq!(
+        *client.metrics(),
+        ClientMetrics {
+            datagrams_sent,
+            octets_sent,
+            datagrams_received: 2,
+            octets_received: 3,
+        }
+    );
+
+    client.reset_metrics();
+    assert_eq!(*client.metrics(), ClientMetrics::default());
+}
+
+/// Replays a fixed sequence of receive results, like a nonblocking socket would return them.
+struct ScriptedReceiver {
+    results: VecDeque<io::Result<Vec<u8>>>,
+}
+
+impl DatagramReceiver for ScriptedReceiver {
+    fn receive(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let datagram = self
+            .results
+            .pop_front()
+            .unwrap_or_else(|| Err(io::ErrorKind::WouldBlock.into()))?;
+        buffer[..datagram.len()].copy_from_slice(&datagram);
+        Ok(datagram.len())
+    }
+}
+
+impl DatagramSender for ScriptedReceiver {
+    fn send(&mut self, _data: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn would_block_ends_receive_batch() {
+    let mut receiver = ScriptedReceiver {
+        results: VecDeque::from([
+            Ok(vec![0x01]),
+            Ok(vec![0x02, 0x03]),
+            Err(io::ErrorKind::WouldBlock.into()),
+            Err(io::ErrorKind::PermissionDenied.into()),
+        ]),
+    };
+    let mut buf = [0u8; 1200];
+
+    let batch = receiver.try_receive_batch(&mut buf, 64).unwrap();
+    assert_eq!(batch, vec![vec![0x01], vec![0x02, 0x03]]);
+
+    let err = receiver.try_receive_batch(&mut buf, 64).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+    assert!(receiver.try_receive_batch(&mut buf, 64).unwrap().is_empty());
+}
+
+#[test]
+fn receive_error_keeps_earlier_datagrams() {
+    let mut receiver = ScriptedReceiver {
+        results: VecDeque::from([
+            Ok(vec![0x01]),
+            Err(io::ErrorKind::ConnectionRefused.into()),
+            Ok(vec![0x02]),
+        ]),
+    };
+    let mut buf = [0u8; 1200];
+
+    let batch = receiver.try_receive_batch(&mut buf, 64).unwrap();
+    assert_eq!(batch, vec![vec![0x01]]);
+
+    let batch = receiver.try_receive_batch(&mut buf, 64).unwrap();
+    assert_eq!(batch, vec![vec![0x02]]);
+}
+
+#[test]
+fn connection_refused_ends_receive_batch() {
+    let mut receiver = ScriptedReceiver {
+        results: VecDeque::from([Err(io::ErrorKind::ConnectionRefused.into()), Ok(vec![0x01])]),
+    };
+    let mut buf = [0u8; 1200];
+
+    assert!(receiver.try_receive_batch(&mut buf, 64).unwrap().is_empty());
+
+    let batch = receiver.try_receive_batch(&mut buf, 64).unwrap();
+    assert_eq!(batch, vec![vec![0x01]]);
+}
+
+#[test]
+fn update_keeps_running_while_host_refuses() {
+    let communicator = ScriptedReceiver {
+        results: VecDeque::from([
+            Err(io::ErrorKind::ConnectionRefused.into()),
+            Err(io::ErrorKind::ConnectionRefused.into()),
+        ]),
+    };
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+
+    client.update(Millis::new(0)).unwrap();
+    client.update(Millis::new(1)).unwrap();
+
+    assert!(client.metrics().datagrams_sent > 0);
+    assert_eq!(client.metrics().datagrams_received, 0);
+}
+
+#[test]
+fn receive_batch_stops_at_max_count() {
+    let (mut communicator, mut host) = LoopbackCommunicator::pair();
+    for octet in 0..3 {
+        host.send(&[octet]).unwrap();
+    }
+    let mut buf = [0u8; 1200];
+
+    let batch = communicator.try_receive_batch(&mut buf, 2).unwrap();
+    assert_eq!(batch, vec![vec![0], vec![1]]);
+    let batch = communicator.try_receive_batch(&mut buf, 2).unwrap();
+    assert_eq!(batch, vec![vec![2]]);
+}
+
+/// Receives the next datagram that the client sent to `host`.
+fn receive_from_client(host: &mut LoopbackCommunicator) -> Vec<u8> {
+    let mut buf = [0u8; 1200];
+    let size = host.receive(&mut buf).unwrap();
+    assert_ne!(size, 0, "client should have sent a datagram");
+    buf[..size].to_vec()
+}
+
+/// Runs the challenge and connect handshake with `host`, which assigns connection id 7.
+fn connect(client: &mut ClientWithCodec<SampleGame, SampleStep>, host: &mut LoopbackCommunicator) {
+    client.update(Millis::new(0)).unwrap();
+    let challenge_request = receive_from_client(host);
+    assert_eq!(challenge_request[0], 0x01);
+    let nonce = &challenge_request[1..9];
+    let request_id = &challenge_request[9..17];
+
+    let mut challenge_response = vec![0x11];
+    challenge_response.extend_from_slice(nonce);
+    challenge_response.extend_from_slice(request_id);
+    challenge_response.extend_from_slice(&0x42u64.to_be_bytes()); // Server challenge
+    host.send(&challenge_response).unwrap();
+    while host.pending_incoming() > 0 {
+        receive_from_client(host);
+    }
+
+    client.update(Millis::new(16)).unwrap();
+    assert!(client.poll_events().is_empty());
+
+    let mut connect_response = vec![0x12];
+    connect_response.extend_from_slice(nonce);
+    connect_response.extend_from_slice(&7u64.to_be_bytes()); // Connection ID
+    host.send(&connect_response).unwrap();
+    client.update(Millis::new(32)).unwrap();
+}
+
+#[test]
+fn connected_event_after_handshake() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+
+    connect(&mut client, &mut host);
+
+    assert_eq!(client.phase(), ClientPhase::Connected(ConnectionId(7)));
+    assert_eq!(
+        client.poll_events(),
+        vec![ClientEvent::Connected {
+            connection_id: ConnectionId(7)
+        }]
+    );
+
+    client.update(Millis::new(48)).unwrap();
+    assert!(client.poll_events().is_empty());
+}
+
+#[test]
+fn silent_host_times_out_connection() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+    client.connection_timeout = Duration::from_millis(100);
+    connect(&mut client, &mut host);
+    client.poll_events();
+
+    client.update(Millis::new(32 + 100)).unwrap();
+    assert_eq!(client.phase(), ClientPhase::Connected(ConnectionId(7)));
+    assert!(client.poll_events().is_empty());
+
+    client.update(Millis::new(32 + 101)).unwrap();
+    assert_eq!(client.phase(), ClientPhase::Disconnected);
+    assert_eq!(
+        client.poll_events(),
+        vec![ClientEvent::Disconnected {
+            connection_id: ConnectionId(7),
+            reason: DisconnectReason::TimedOut,
+        }]
+    );
+}
+
+#[test]
+fn drop_sends_disconnect_after_connecting() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let mut client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+    connect(&mut client, &mut host);
+    while host.pending_incoming() > 0 {
+        receive_from_client(&mut host);
+    }
+
+    drop(client);
+
+    let disconnect = receive_from_client(&mut host);
+    assert_eq!(disconnect[0], 0x04);
+    assert_eq!(&disconnect[1..9], &7u64.to_be_bytes());
+    assert_eq!(host.pending_incoming(), 0);
+}
+
+#[test]
+fn close_with_chained_codec_sends_bare_disconnect() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let connection = SharedConnection::new(Box::new(GetRandom));
+    let codec = ChainedCodec::new(connection.clone(), MarkerCodec(0x0b));
+    let mut client = ClientWithCodec::<SampleGame, SampleStep>::with_codec(
+        Box::new(communicator),
+        connection,
+        Box::new(codec),
+    );
+    connect(&mut client, &mut host);
+    while host.pending_incoming() > 0 {
+        receive_from_client(&mut host);
+    }
+
+    client.close().unwrap();
+
+    let disconnect = receive_from_client(&mut host);
+    assert_eq!(disconnect, [&[0x04][..], &7u64.to_be_bytes()].concat());
+}
+
+#[test]
+fn drop_before_connecting_sends_nothing() {
+    let (communicator, host) = LoopbackCommunicator::pair();
+    let client =
+        ClientWithCodec::<SampleGame, SampleStep>::with_communicator(Box::new(communicator));
+
+    drop(client);
+
+    assert_eq!(host.pending_incoming(), 0);
+}
+
+struct IdentityCodec;
+
+impl DatagramEncoder for IdentityCodec {
+    fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(buf.to_vec())
+    }
+}
+
+impl DatagramDecoder for IdentityCodec {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(buf.to_vec())
+    }
+}
+
+struct ReversingCodec;
+
+impl DatagramEncoder for ReversingCodec {
+    fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(buf.iter().rev().copied().collect())
+    }
+}
+
+impl DatagramDecoder for ReversingCodec {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(buf.iter().rev().copied().collect())
+    }
+}
+
+/// Adds a marker octet in front of the payload, and requires it when decoding.
+struct MarkerCodec(u8);
+
+impl DatagramEncoder for MarkerCodec {
+    fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        Ok([&[self.0], buf].concat())
+    }
+}
+
+impl DatagramDecoder for MarkerCodec {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        match buf.split_first() {
+            Some((marker, payload)) if *marker == self.0 => Ok(payload.to_vec()),
+            _ => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+#[test]
+fn chained_codec_round_trips() {
+    let mut codec = ChainedCodec::new(IdentityCodec, ReversingCodec);
+
+    let encoded = codec.encode(&[1, 2, 3]).unwrap();
+    assert_eq!(encoded, vec![3, 2, 1]);
+    assert_eq!(codec.decode(&encoded).unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn chained_codec_lower_layer_is_outermost() {
+    let mut codec = ChainedCodec::new(MarkerCodec(0x0a), MarkerCodec(0x0b));
+
+    let encoded = codec.encode(&[0x42]).unwrap();
+    assert_eq!(encoded, vec![0x0a, 0x0b, 0x42]);
+    assert_eq!(codec.decode(&encoded).unwrap(), vec![0x42]);
+
+    let err = codec.decode(&[0x0b, 0x0a, 0x42]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn update_runs_chained_codec_over_connection() {
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let connection = SharedConnection::new(Box::new(GetRandom));
+    let codec = ChainedCodec::new(connection.clone(), MarkerCodec(0x0b));
+    let mut client = ClientWithCodec::<SampleGame, SampleStep>::with_codec(
+        Box::new(communicator),
+        connection,
+        Box::new(codec),
+    );
+
+    client.update(Millis::new(0)).unwrap();
+    let challenge_request = receive_from_client(&mut host);
+    assert_eq!(challenge_request[0], 0x01);
+    assert_eq!(challenge_request[17], 0x0b);
+
+    connect(&mut client, &mut host);
+    assert_eq!(
+        client.poll_events(),
+        vec![ClientEvent::Connected {
+            connection_id: ConnectionId(7)
+        }]
+    );
+
+    while host.pending_incoming() > 0 {
+        receive_from_client(&mut host);
+    }
+    client.update(Millis::new(48)).unwrap();
+    let packet = receive_from_client(&mut host);
+    assert_eq!(packet[0], 0x03);
+    assert_eq!(packet[11], 0x0b);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressing_codec_round_trips() {
+    use nimble_client_with_codec::CompressingCodec;
+
+    let mut codec = CompressingCodec::default();
+
+    let tiny = [0x01, 0x02, 0x03];
+    let encoded = codec.encode(&tiny).unwrap();
+    assert_eq!(encoded, vec![0x00, 0x01, 0x02, 0x03]);
+    assert_eq!(codec.decode(&encoded).unwrap(), tiny);
+
+    let large: Vec<u8> = (0..1000).map(|index| (index % 8) as u8).collect();
+    let encoded = codec.encode(&large).unwrap();
+    assert_eq!(encoded[0], 0x01);
+    assert!(encoded.len() < large.len() / 2);
+    assert_eq!(codec.decode(&encoded).unwrap(), large);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn update_runs_compressing_codec_over_connection() {
+    use nimble_client_with_codec::CompressingCodec;
+
+    let (communicator, mut host) = LoopbackCommunicator::pair();
+    let connection = SharedConnection::new(Box::new(GetRandom));
+    let codec = ChainedCodec::new(connection.clone(), CompressingCodec::default());
+    let mut client = ClientWithCodec::<SampleGame, SampleStep>::with_codec(
+        Box::new(communicator),
+        connection,
+        Box::new(codec),
+    );
+
+    connect(&mut client, &mut host);
+    assert_eq!(client.phase(), ClientPhase::Connected(ConnectionId(7)));
+
+    while host.pending_incoming() > 0 {
+        receive_from_client(&mut host);
+    }
+    client.update(Millis::new(48)).unwrap();
+    let packet = receive_from_client(&mut host);
+    assert_eq!(packet[0], 0x03);
+    assert_eq!(packet[11], 0x00); // Small payloads are sent uncompressed
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressing_codec_rejects_oversized_claim() {
+    use nimble_client_with_codec::CompressingCodec;
+
+    let mut codec = CompressingCodec::default();
+    let mut datagram = vec![0x01];
+    datagram.extend_from_slice(&u32::MAX.to_le_bytes());
+
+    let err = codec.decode(&datagram).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,22 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use mash_rs::murmur3_32;
+
+/// Computes the checksum written into the connection header to verify the payload.
+///
+/// Both ends of a connection must use the same algorithm.
+pub trait ChecksumAlgorithm {
+    fn compute(&self, payload: &[u8], seed: u32) -> u32;
+}
+
+/// The default checksum, a 32-bit [Murmur3 hash](https://en.wikipedia.org/wiki/MurmurHash#MurmurHash3).
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Murmur3;
+
+impl ChecksumAlgorithm for Murmur3 {
+    fn compute(&self, payload: &[u8], seed: u32) -> u32 {
+        murmur3_32(payload, seed)
+    }
+}
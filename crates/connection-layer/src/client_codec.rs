@@ -1,11 +1,15 @@
 use crate::client_to_host::{ClientToHostCommands, ConnectRequest};
+use crate::error::header_truncated;
 use crate::host_to_client::HostToClientCommands;
-use crate::{verify_hash, write_to_stream, ConnectionId, ConnectionSecretSeed, RequestId, Version};
+use crate::{
+    verify_hash_with, write_to_stream_with, ChecksumAlgorithm, ConnectionId, ConnectionSecretSeed,
+    Murmur3, RequestId, Version, HEADER_SIZE,
+};
 use datagram::{DatagramDecoder, DatagramEncoder};
 use flood_rs::in_stream::InOctetStream;
 use flood_rs::out_stream::OutOctetStream;
 use flood_rs::{Deserialize, ReadOctetStream, Serialize};
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use std::io;
 
 pub struct ConnectionInfo {
@@ -13,21 +17,30 @@ pub struct ConnectionInfo {
     pub seed: ConnectionSecretSeed,
 }
 
-pub struct ConnectionLayerClientCodec {
+pub struct ConnectionLayerClientCodec<C: ChecksumAlgorithm = Murmur3> {
     pub connection_info: Option<ConnectionInfo>,
     pub request_id: RequestId,
+    checksum: C,
 }
 
 impl ConnectionLayerClientCodec {
     pub fn new(request_id: RequestId) -> Self {
+        Self::with_checksum(request_id, Murmur3)
+    }
+}
+
+impl<C: ChecksumAlgorithm> ConnectionLayerClientCodec<C> {
+    /// Creates a client codec that uses `checksum` instead of Murmur3. The host must use the same algorithm.
+    pub fn with_checksum(request_id: RequestId, checksum: C) -> Self {
         Self {
             connection_info: None,
             request_id,
+            checksum,
         }
     }
 }
 
-impl DatagramEncoder for ConnectionLayerClientCodec {
+impl<C: ChecksumAlgorithm> DatagramEncoder for ConnectionLayerClientCodec<C> {
     fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
         let mut stream = OutOctetStream::new();
         match &self.connection_info {
@@ -35,7 +48,7 @@ impl DatagramEncoder for ConnectionLayerClientCodec {
                 ConnectionId { value: 0 }.to_stream(&mut stream)?;
                 let connect_request = ConnectRequest {
                     request_id: self.request_id,
-                    version: Version { major: 0, minor: 2 },
+                    version: Version::CURRENT,
                 };
                 debug!("client sending connect request {connect_request:?}");
                 ClientToHostCommands::Connect(connect_request).serialize(&mut stream)?;
@@ -43,12 +56,14 @@ impl DatagramEncoder for ConnectionLayerClientCodec {
             }
             Some(connection_info) => {
                 trace!(
+                    connection_id = connection_info.connection_id.value;
                     "client sending payload connection_id: {} size: {}",
                     connection_info.connection_id.value,
                     buf.len()
                 );
 
-                write_to_stream(
+                write_to_stream_with(
+                    &self.checksum,
                     &mut stream,
                     connection_info.connection_id,
                     connection_info.seed,
@@ -62,10 +77,10 @@ impl DatagramEncoder for ConnectionLayerClientCodec {
     }
 }
 
-impl DatagramDecoder for ConnectionLayerClientCodec {
+impl<C: ChecksumAlgorithm> DatagramDecoder for ConnectionLayerClientCodec<C> {
     fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
         let mut in_stream = InOctetStream::new(buf);
-        let connection_id = ConnectionId::from_stream(&mut in_stream)?;
+        let connection_id = ConnectionId::from_stream(&mut in_stream).map_err(header_truncated)?;
 
         match &self.connection_info {
             None => {
@@ -85,14 +100,21 @@ impl DatagramDecoder for ConnectionLayerClientCodec {
                 if connection_id != connection_info.connection_id {
                     Err(io::Error::new(io::ErrorKind::InvalidData, "problem"))
                 } else {
-                    let murmur = in_stream.read_u32()?;
-                    verify_hash(murmur, connection_info.seed, &buf[5..])?;
+                    let murmur = in_stream.read_u32().map_err(header_truncated)?;
+                    verify_hash_with(
+                        &self.checksum,
+                        murmur,
+                        connection_info.seed,
+                        &buf[HEADER_SIZE..],
+                    )
+                    .inspect_err(|err| warn!(connection_id = connection_id.value; "{err}"))?;
                     debug!(
+                        connection_id = connection_id.value;
                         "client received payload size:{} connection:{}",
-                        buf.len() - 5,
+                        buf.len() - HEADER_SIZE,
                         connection_id.value
                     );
-                    Ok(buf[5..].to_vec())
+                    Ok(buf[HEADER_SIZE..].to_vec())
                 }
             }
         }
@@ -0,0 +1,85 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use hexify::format_hex_u32_be;
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+/// Errors from verifying or writing the connection header.
+#[derive(Debug)]
+pub enum ConnectionLayerError {
+    /// The hash calculated over the payload does not match the hash in the header.
+    HashMismatch {
+        expected: u32,
+        calculated: u32,
+    },
+    /// The buffer ended before the connection header did.
+    Truncated,
+    /// A connect request asked for a connection layer version newer than the host allows.
+    ImplausibleVersion {
+        major: u8,
+        minor: u8,
+    },
+    Io(io::Error),
+}
+
+impl Display for ConnectionLayerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HashMismatch {
+                expected,
+                calculated,
+            } => write!(
+                f,
+                "hash mismatch: the data does not match the expected hash. calculated {} but payload provided hash {}",
+                format_hex_u32_be(*calculated),
+                format_hex_u32_be(*expected),
+            ),
+            Self::Truncated => write!(f, "connection header is truncated"),
+            Self::ImplausibleVersion { major, minor } => {
+                write!(f, "implausible connection layer version {major}.{minor}")
+            }
+            Self::Io(err) => write!(f, "io error: {err}"),
+        }
+    }
+}
+
+impl error::Error for ConnectionLayerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Reports a short read of the connection header as [`ConnectionLayerError::Truncated`].
+///
+/// Other errors are passed on unchanged.
+pub(crate) fn header_truncated(err: io::Error) -> io::Error {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        ConnectionLayerError::Truncated.into()
+    } else {
+        err
+    }
+}
+
+impl From<io::Error> for ConnectionLayerError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ConnectionLayerError> for io::Error {
+    fn from(err: ConnectionLayerError) -> Self {
+        match err {
+            ConnectionLayerError::Io(err) => err,
+            ConnectionLayerError::HashMismatch { .. }
+            | ConnectionLayerError::ImplausibleVersion { .. } => {
+                io::Error::new(io::ErrorKind::InvalidData, err)
+            }
+            ConnectionLayerError::Truncated => io::Error::new(io::ErrorKind::UnexpectedEof, err),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use crate::{
+    write_empty, ChecksumAlgorithm, ConnectionId, ConnectionLayer, ConnectionLayerError,
+    ConnectionLayerMode, ConnectionSecretSeed, Murmur3, ToOctets,
+};
+use flood_rs::out_stream::OutOctetStream;
+use std::io::Result;
+
+/// Size of the connection header on the wire: the `u16` connection id followed by the `u32` hash.
+pub const HEADER_SIZE: usize = 2 + 4;
+
+/// Writes a connection header before the payload is known, and fills it in afterwards.
+///
+/// [`ConnectionHeaderWriter::reserve`] writes a zeroed placeholder header and remembers
+/// where it is. Once the payload has been written after it,
+/// [`ConnectionHeaderWriter::finalize`] hashes everything following the header and
+/// patches the real header into the reserved region.
+#[derive(Debug, Default)]
+pub struct ConnectionHeaderWriter<C: ChecksumAlgorithm = Murmur3> {
+    offset: usize,
+    checksum: C,
+}
+
+impl ConnectionHeaderWriter {
+    pub fn new() -> Self {
+        Self::with_checksum(Murmur3)
+    }
+}
+
+impl<C: ChecksumAlgorithm> ConnectionHeaderWriter<C> {
+    /// Creates a header writer that uses `checksum` instead of Murmur3.
+    pub fn with_checksum(checksum: C) -> Self {
+        Self {
+            offset: 0,
+            checksum,
+        }
+    }
+
+    /// Writes a placeholder header of [`HEADER_SIZE`] octets at the end of `stream`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Result` error if writing to the stream fails.
+    pub fn reserve(&mut self, stream: &mut OutOctetStream) -> Result<()> {
+        self.offset = stream.octets_ref().len();
+        write_empty(stream)
+    }
+
+    /// Hashes the payload that follows the reserved header in `buf` and writes the
+    /// final header over the placeholder.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConnectionLayerError::Truncated`] if `buf` is too short to contain
+    /// the reserved header.
+    pub fn finalize(
+        &self,
+        buf: &mut [u8],
+        connection_id: ConnectionId,
+        seed: ConnectionSecretSeed,
+    ) -> std::result::Result<(), ConnectionLayerError> {
+        if buf.len() < self.offset + HEADER_SIZE {
+            return Err(ConnectionLayerError::Truncated);
+        }
+
+        let (header, payload) = buf[self.offset..].split_at_mut(HEADER_SIZE);
+        let mode = ConnectionLayerMode::Connection(ConnectionLayer {
+            connection_id,
+            murmur3_hash: self.checksum.compute(payload, seed.0),
+        });
+        header.copy_from_slice(&mode.to_octets()?);
+
+        Ok(())
+    }
+}
@@ -1,18 +1,26 @@
 use crate::client_to_host::ClientToHostCommands;
+use crate::error::header_truncated;
 use crate::host_to_client::{ConnectResponse, HostToClientCommands};
-use crate::{verify_hash, write_to_stream, ConnectionId, ConnectionSecretSeed, RequestId};
+use crate::{
+    verify_hash_with, write_to_stream_with, ChecksumAlgorithm, ConnectionId, ConnectionSecretSeed,
+    Murmur3, RequestId, Version, HEADER_SIZE,
+};
 use flood_rs::in_stream::InOctetStream;
 use flood_rs::out_stream::OutOctetStream;
 use flood_rs::{Deserialize, ReadOctetStream, Serialize};
 use freelist_rs::FreeList;
-use log::{debug, trace};
+use log::{debug, trace, warn};
+use monotonic_time_rs::Millis;
 use secure_random::SecureRandom;
 use std::collections::HashMap;
 use std::io;
 use std::io::ErrorKind;
 
+/// Default for [`ConnectionLayerHostCodec::idle_timeout_ms`].
+pub const DEFAULT_IDLE_TIMEOUT_MS: u64 = 10_000;
+
 pub trait DatagramHostEncoder {
-    fn encode(&mut self, connection_id: u8, buf: &[u8]) -> io::Result<Vec<u8>>;
+    fn encode(&mut self, connection_id: u16, buf: &[u8]) -> io::Result<Vec<u8>>;
 }
 
 pub struct HostConnection {
@@ -20,29 +28,82 @@ pub struct HostConnection {
     pub connection_id: ConnectionId,
     pub seed: ConnectionSecretSeed,
     pub has_received_connect: bool,
+    /// Codec time of the last datagram received on this connection.
+    pub last_activity: Millis,
 }
 
-pub struct ConnectionLayerHostCodec {
-    pub connection_ids: FreeList<u8>,
-    pub connections: HashMap<u8, HostConnection>,
+pub struct ConnectionLayerHostCodec<C: ChecksumAlgorithm = Murmur3> {
+    pub connection_ids: FreeList<u16>,
+    pub connections: HashMap<u16, HostConnection>,
     pub random: Box<dyn SecureRandom>,
+    /// Connect requests with a higher major connection layer version are rejected.
+    /// Defaults to the major version of this crate.
+    pub max_major_version: u8,
+    /// Connections that have not sent anything for longer than this are evicted by
+    /// [`ConnectionLayerHostCodec::tick`].
+    pub idle_timeout_ms: u64,
+    now: Millis,
+    checksum: C,
 }
 
 impl ConnectionLayerHostCodec {
     pub fn new(random: Box<dyn SecureRandom>) -> Self {
+        Self::with_checksum(random, Murmur3)
+    }
+}
+
+impl<C: ChecksumAlgorithm> ConnectionLayerHostCodec<C> {
+    /// Creates a host codec that uses `checksum` instead of Murmur3. Clients must use the same algorithm.
+    pub fn with_checksum(random: Box<dyn SecureRandom>, checksum: C) -> Self {
         let mut s = Self {
             connections: HashMap::new(),
-            connection_ids: FreeList::new(0xff),
+            connection_ids: FreeList::new(0xffff),
             random,
+            max_major_version: Version::CURRENT.major,
+            idle_timeout_ms: DEFAULT_IDLE_TIMEOUT_MS,
+            now: Millis::new(0),
+            checksum,
         };
         s.connection_ids.allocate(); // Reserve zero
 
         s
     }
+
+    /// Advances the codec time to `now` and evicts the connections that have been idle
+    /// for longer than [`Self::idle_timeout_ms`], freeing their connection ids.
+    ///
+    /// Datagrams decoded after this call count as activity at `now`.
+    /// Returns the evicted connection ids, lowest first.
+    pub fn tick(&mut self, now: Millis) -> Vec<ConnectionId> {
+        self.now = now;
+        let mut idle: Vec<u16> = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| {
+                now.absolute_milliseconds()
+                    .saturating_sub(connection.last_activity.absolute_milliseconds())
+                    > self.idle_timeout_ms
+            })
+            .map(|(connection_id, _)| *connection_id)
+            .collect();
+        idle.sort_unstable();
+
+        for connection_id in &idle {
+            self.connections.remove(connection_id);
+            if self.connection_ids.free(*connection_id).is_err() {
+                warn!(connection_id = connection_id; "connection id was already free");
+            }
+            debug!(connection_id = connection_id; "evicted idle connection {connection_id}");
+        }
+
+        idle.into_iter()
+            .map(|value| ConnectionId { value })
+            .collect()
+    }
 }
 
-impl DatagramHostEncoder for ConnectionLayerHostCodec {
-    fn encode(&mut self, connection_id: u8, buf: &[u8]) -> io::Result<Vec<u8>> {
+impl<C: ChecksumAlgorithm> DatagramHostEncoder for ConnectionLayerHostCodec<C> {
+    fn encode(&mut self, connection_id: u16, buf: &[u8]) -> io::Result<Vec<u8>> {
         let connection = self.connections.get_mut(&connection_id);
         if connection.is_none() {
             Err(io::Error::new(
@@ -54,11 +115,13 @@ impl DatagramHostEncoder for ConnectionLayerHostCodec {
         let mut stream = OutOctetStream::new();
         if actual_connection.has_received_connect {
             trace!(
+                connection_id = actual_connection.connection_id.value;
                 "host sending on connection {} size: {}",
                 actual_connection.connection_id.value,
                 buf.len()
             );
-            write_to_stream(
+            write_to_stream_with(
+                &self.checksum,
                 &mut stream,
                 actual_connection.connection_id,
                 actual_connection.seed,
@@ -66,6 +129,7 @@ impl DatagramHostEncoder for ConnectionLayerHostCodec {
             )?;
         } else {
             debug!(
+                connection_id = actual_connection.connection_id.value;
                 "host sending connect response connection_id: {} for request: {}",
                 actual_connection.connection_id.value, actual_connection.created_from_request
             );
@@ -85,24 +149,27 @@ impl DatagramHostEncoder for ConnectionLayerHostCodec {
 }
 
 pub trait DatagramHostDecoder {
-    fn decode(&mut self, buf: &[u8]) -> io::Result<(u8, Vec<u8>)>;
+    fn decode(&mut self, buf: &[u8]) -> io::Result<(u16, Vec<u8>)>;
 }
 
-impl DatagramHostDecoder for ConnectionLayerHostCodec {
-    fn decode(&mut self, buf: &[u8]) -> io::Result<(u8, Vec<u8>)> {
+impl<C: ChecksumAlgorithm> DatagramHostDecoder for ConnectionLayerHostCodec<C> {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<(u16, Vec<u8>)> {
         let mut in_stream = InOctetStream::new(buf);
-        let connection_id = ConnectionId::from_stream(&mut in_stream)?;
+        let connection_id = ConnectionId::from_stream(&mut in_stream).map_err(header_truncated)?;
         if connection_id.value != 0 {
             if let Some(connection) = self.connections.get_mut(&connection_id.value) {
-                let murmur = in_stream.read_u32()?;
-                verify_hash(murmur, connection.seed, &buf[5..])?;
+                let murmur = in_stream.read_u32().map_err(header_truncated)?;
+                verify_hash_with(&self.checksum, murmur, connection.seed, &buf[HEADER_SIZE..])
+                    .inspect_err(|err| warn!(connection_id = connection_id.value; "{err}"))?;
                 trace!(
+                    connection_id = connection_id.value;
                     "host received payload of size: {} from connection {}",
-                    buf.len() - 5,
+                    buf.len() - HEADER_SIZE,
                     connection.connection_id.value
                 );
 
                 connection.has_received_connect = true;
+                connection.last_activity = self.now;
                 //                Ok(buf[5..].to_vec())
                 Ok((
                     connection_id.value,
@@ -120,6 +187,7 @@ impl DatagramHostDecoder for ConnectionLayerHostCodec {
             match command {
                 ClientToHostCommands::Connect(connect_request) => {
                     debug!("host received connect request {connect_request:?}");
+                    connect_request.version.validate(self.max_major_version)?;
                     let assigned_connection_id = self.connection_ids.allocate().ok_or(
                         io::Error::new(io::ErrorKind::InvalidData, "free list problem"),
                     )?;
@@ -130,6 +198,7 @@ impl DatagramHostDecoder for ConnectionLayerHostCodec {
                         },
                         seed: ConnectionSecretSeed(self.random.random_u64() as u32),
                         has_received_connect: false,
+                        last_activity: self.now,
                     };
                     self.connections
                         .insert(assigned_connection_id, new_connection);
@@ -35,7 +35,7 @@ impl Serialize for ConnectResponse {
         Self: Sized,
     {
         stream.write_u64(self.request_id)?;
-        stream.write_u8(self.connection_id.value)?;
+        self.connection_id.to_stream(stream)?;
         stream.write_u32(self.seed.0)
     }
 }
@@ -47,9 +47,7 @@ impl Deserialize for ConnectResponse {
     {
         Ok(Self {
             request_id: stream.read_u64()?,
-            connection_id: ConnectionId {
-                value: stream.read_u8()?,
-            },
+            connection_id: ConnectionId::from_stream(stream)?,
             seed: ConnectionSecretSeed(stream.read_u32()?),
         })
     }
@@ -2,26 +2,33 @@
  * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
+mod checksum;
 mod client_codec;
 mod client_to_host;
+mod error;
+mod header;
 mod host_codec;
 mod host_to_client;
+mod parser;
+mod pipeline;
 pub mod prelude;
 
+pub use checksum::{ChecksumAlgorithm, Murmur3};
+pub use datagram_connections::{FromOctets, ToOctets};
+pub use error::ConnectionLayerError;
 use flood_rs::prelude::*;
-use hexify::format_hex_u32_be;
-use mash_rs::murmur3_32;
+pub use header::{ConnectionHeaderWriter, HEADER_SIZE};
 use std::io;
-use std::io::{Error, ErrorKind, Result};
+use std::io::Result;
 
 pub type RequestId = u64; // So it is very likely that this number will change for each connection attempt
 
-/// A seed used for generating a [Murmur3 hash](https://en.wikipedia.org/wiki/MurmurHash#MurmurHash3) for connection validation.
-
 /// Represents a unique connection identifier for the session.
+///
+/// Zero is reserved for [OOB](ConnectionLayerMode::OOB) datagrams.
 #[derive(Eq, PartialEq, Copy, Clone, Default, Debug)]
 pub struct ConnectionId {
-    pub value: u8,
+    pub value: u16,
 }
 
 impl ConnectionId {
@@ -35,7 +42,7 @@ impl ConnectionId {
     ///
     /// Returns an `io::Result` error if writing to the stream fails.
     pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
-        stream.write_u8(self.value)
+        stream.write_u16(self.value)
     }
 
     /// Reads a connection identifier from the provided input stream.
@@ -49,15 +56,31 @@ impl ConnectionId {
     /// A `Result` containing the `ConnectionId` if successful, or an `io::Result` error if reading fails.
     pub fn from_stream(stream: &mut impl ReadOctetStream) -> Result<Self> {
         Ok(Self {
-            value: stream.read_u8()?,
+            value: stream.read_u16()?,
         })
     }
 }
 
+impl ToOctets for ConnectionId {
+    fn write_octets(&self, stream: &mut OutOctetStream) -> Result<()> {
+        self.to_stream(stream)
+    }
+}
+
+impl FromOctets for ConnectionId {
+    fn read_octets(stream: &mut InOctetStream) -> Result<Self> {
+        Self::from_stream(stream)
+    }
+}
+
 /// Represents the header of a connection with an ID and a Murmur3 hash.
+///
+/// On the wire this is the connection id as a big-endian `u16` followed by the hash as a big-endian `u32`.
 #[derive(Eq, PartialEq, Debug)]
 pub struct ConnectionLayer {
     pub connection_id: ConnectionId,
+    /// [`murmur3_32`](mash_rs::murmur3_32) (x86, 32-bit) of the payload octets as given,
+    /// seeded with the [`ConnectionSecretSeed`].
     pub murmur3_hash: u32,
 }
 
@@ -111,8 +134,21 @@ impl ConnectionLayerMode {
     }
 }
 
+impl ToOctets for ConnectionLayerMode {
+    fn write_octets(&self, stream: &mut OutOctetStream) -> Result<()> {
+        self.to_stream(stream)
+    }
+}
+
+impl FromOctets for ConnectionLayerMode {
+    fn read_octets(stream: &mut InOctetStream) -> Result<Self> {
+        Self::from_stream(stream)
+    }
+}
+
+/// A seed used for generating a [Murmur3 hash](https://en.wikipedia.org/wiki/MurmurHash#MurmurHash3) for connection validation.
 #[derive(Debug, Copy, Clone)]
-pub struct ConnectionSecretSeed(u32);
+pub struct ConnectionSecretSeed(pub u32);
 
 /// Writes a connection header and a payload to the provided stream, including a Murmur3 hash for validation.
 ///
@@ -132,7 +168,22 @@ pub fn write_to_stream(
     seed: ConnectionSecretSeed,
     payload: &[u8],
 ) -> Result<()> {
-    let calculated_hash = murmur3_32(payload, seed.0);
+    write_to_stream_with(&Murmur3, stream, connection_id, seed, payload)
+}
+
+/// Same as [`write_to_stream`], but uses `checksum` instead of Murmur3 for the header hash.
+///
+/// # Errors
+///
+/// Returns an `io::Result` error if writing to the stream fails.
+pub fn write_to_stream_with(
+    checksum: &impl ChecksumAlgorithm,
+    stream: &mut impl WriteOctetStream,
+    connection_id: ConnectionId,
+    seed: ConnectionSecretSeed,
+    payload: &[u8],
+) -> Result<()> {
+    let calculated_hash = checksum.compute(payload, seed.0);
     ConnectionLayerMode::Connection(ConnectionLayer {
         connection_id,
         murmur3_hash: calculated_hash,
@@ -159,17 +210,32 @@ pub fn write_empty(stream: &mut impl WriteOctetStream) -> Result<()> {
 ///
 /// # Errors
 ///
-/// Returns an `io::Result` error if the calculated hash does not match the expected hash.
-pub fn verify_hash(expected_hash: u32, seed: ConnectionSecretSeed, payload: &[u8]) -> Result<()> {
-    let calculated_hash = murmur3_32(payload, seed.0);
+/// Returns [`ConnectionLayerError::HashMismatch`] if the calculated hash does not match the expected hash.
+pub fn verify_hash(
+    expected_hash: u32,
+    seed: ConnectionSecretSeed,
+    payload: &[u8],
+) -> std::result::Result<(), ConnectionLayerError> {
+    verify_hash_with(&Murmur3, expected_hash, seed, payload)
+}
+
+/// Same as [`verify_hash`], but uses `checksum` instead of Murmur3 to calculate the hash.
+///
+/// # Errors
+///
+/// Returns [`ConnectionLayerError::HashMismatch`] if the calculated hash does not match the expected hash.
+pub fn verify_hash_with(
+    checksum: &impl ChecksumAlgorithm,
+    expected_hash: u32,
+    seed: ConnectionSecretSeed,
+    payload: &[u8],
+) -> std::result::Result<(), ConnectionLayerError> {
+    let calculated_hash = checksum.compute(payload, seed.0);
     if calculated_hash != expected_hash {
-        Err(Error::new(
-            ErrorKind::InvalidData,
-            format!(
-                "hash mismatch: the data does not match the expected hash. calculated {} but payload provided hash {}",
-                format_hex_u32_be(calculated_hash), format_hex_u32_be(expected_hash),
-            ),
-        ))
+        Err(ConnectionLayerError::HashMismatch {
+            expected: expected_hash,
+            calculated: calculated_hash,
+        })
     } else {
         Ok(())
     }
@@ -181,6 +247,22 @@ struct Version {
     pub minor: u8,
 }
 
+impl Version {
+    /// The connection layer version that this crate speaks.
+    const CURRENT: Self = Self { major: 0, minor: 2 };
+
+    /// Rejects a version read from untrusted input whose major version is above `max_major`.
+    fn validate(&self, max_major: u8) -> std::result::Result<(), ConnectionLayerError> {
+        if self.major > max_major {
+            return Err(ConnectionLayerError::ImplausibleVersion {
+                major: self.major,
+                minor: self.minor,
+            });
+        }
+        Ok(())
+    }
+}
+
 impl Serialize for Version {
     fn serialize(&self, stream: &mut impl WriteOctetStream) -> io::Result<()>
     where
@@ -0,0 +1,71 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use crate::error::header_truncated;
+use crate::{
+    verify_hash_with, ChecksumAlgorithm, ConnectionId, ConnectionLayerMode, ConnectionSecretSeed,
+    Murmur3,
+};
+use datagram::DatagramParser;
+use flood_rs::in_stream::InOctetStream;
+use log::warn;
+use std::io;
+use std::io::ErrorKind;
+
+/// Parses datagrams for an established connection without copying the payload.
+///
+/// The connection header is validated against `connection_id`, the checksum is
+/// verified over the payload, and the payload is returned as a subslice of the datagram.
+pub struct ConnectionLayerParser<C: ChecksumAlgorithm = Murmur3> {
+    pub connection_id: ConnectionId,
+    pub seed: ConnectionSecretSeed,
+    checksum: C,
+}
+
+impl ConnectionLayerParser {
+    pub fn new(connection_id: ConnectionId, seed: ConnectionSecretSeed) -> Self {
+        Self::with_checksum(connection_id, seed, Murmur3)
+    }
+}
+
+impl<C: ChecksumAlgorithm> ConnectionLayerParser<C> {
+    pub fn with_checksum(
+        connection_id: ConnectionId,
+        seed: ConnectionSecretSeed,
+        checksum: C,
+    ) -> Self {
+        Self {
+            connection_id,
+            seed,
+            checksum,
+        }
+    }
+}
+
+impl<C: ChecksumAlgorithm> DatagramParser for ConnectionLayerParser<C> {
+    fn parse<'a>(&mut self, buf: &'a [u8]) -> io::Result<&'a [u8]> {
+        let mut in_stream = InOctetStream::new(buf);
+        match ConnectionLayerMode::from_stream(&mut in_stream).map_err(header_truncated)? {
+            ConnectionLayerMode::OOB => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "expected a connection datagram, but received an OOB datagram",
+            )),
+            ConnectionLayerMode::Connection(layer) => {
+                if layer.connection_id != self.connection_id {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "wrong connection id. expected {} but received {}",
+                            self.connection_id.value, layer.connection_id.value
+                        ),
+                    ));
+                }
+                let payload = &buf[in_stream.cursor.position() as usize..];
+                verify_hash_with(&self.checksum, layer.murmur3_hash, self.seed, payload)
+                    .inspect_err(|err| warn!(connection_id = layer.connection_id.value; "{err}"))?;
+                Ok(payload)
+            }
+        }
+    }
+}
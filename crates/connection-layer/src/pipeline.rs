@@ -0,0 +1,99 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use crate::parser::ConnectionLayerParser;
+use crate::{ChecksumAlgorithm, ConnectionId, ConnectionSecretSeed, Murmur3};
+use datagram::DatagramParser;
+use flood_rs::in_stream::InOctetStream;
+use flood_rs::{Deserialize, ReadOctetStream};
+use log::{trace, warn};
+use std::io;
+use std::io::ErrorKind;
+
+/// Receives the datagrams from the host on an established connection.
+///
+/// Every client runs the same steps for a connection datagram, and this does them once:
+///
+/// 1. Parses the connection header and checks the connection id.
+/// 2. Verifies the hash over the payload.
+/// 3. Rejects a datagram that is a duplicate of, or older than, the last accepted one,
+///    using the big-endian `u16` datagram id that starts the payload. The id wraps around,
+///    and an id up to half the range ahead of the last one counts as newer.
+/// 4. Deserializes the commands that follow, one at a time, until the datagram ends.
+///
+/// The commands belong to the protocol on top of the connection layer, so any
+/// [`Deserialize`] type can be used for them.
+pub struct DatagramReceivePipeline<C: ChecksumAlgorithm = Murmur3> {
+    parser: ConnectionLayerParser<C>,
+    last_datagram_id: Option<u16>,
+}
+
+impl DatagramReceivePipeline {
+    pub fn new(connection_id: ConnectionId, seed: ConnectionSecretSeed) -> Self {
+        Self::with_checksum(connection_id, seed, Murmur3)
+    }
+}
+
+impl<C: ChecksumAlgorithm> DatagramReceivePipeline<C> {
+    /// Creates a pipeline that uses `checksum` instead of Murmur3. The host must use the same algorithm.
+    pub fn with_checksum(
+        connection_id: ConnectionId,
+        seed: ConnectionSecretSeed,
+        checksum: C,
+    ) -> Self {
+        Self {
+            parser: ConnectionLayerParser::with_checksum(connection_id, seed, checksum),
+            last_datagram_id: None,
+        }
+    }
+
+    /// Runs `datagram` through the pipeline and calls `on_command` for every command in it,
+    /// in the order they were written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ErrorKind::InvalidData`] error for a wrong connection id, a hash
+    /// mismatch or a datagram that is out of order. Nothing is handed to `on_command` then.
+    /// A command that fails to deserialize ends the datagram with its error, after the
+    /// commands before it have been handed to `on_command`.
+    pub fn receive<CommandT: Deserialize>(
+        &mut self,
+        datagram: &[u8],
+        mut on_command: impl FnMut(CommandT),
+    ) -> io::Result<()> {
+        let payload = self.parser.parse(datagram)?;
+        let mut in_stream = InOctetStream::new(payload);
+        let datagram_id = in_stream.read_u16()?;
+        self.accept_datagram_id(datagram_id)?;
+
+        while (in_stream.cursor.position() as usize) < payload.len() {
+            on_command(CommandT::deserialize(&mut in_stream)?);
+        }
+        trace!(
+            connection_id = self.parser.connection_id.value;
+            "received datagram {datagram_id} with {} octets of commands",
+            payload.len() - 2
+        );
+
+        Ok(())
+    }
+
+    fn accept_datagram_id(&mut self, datagram_id: u16) -> io::Result<()> {
+        if let Some(last) = self.last_datagram_id {
+            let distance = datagram_id.wrapping_sub(last);
+            if distance == 0 || distance > u16::MAX / 2 {
+                warn!(
+                    connection_id = self.parser.connection_id.value;
+                    "dropping datagram {datagram_id}, last accepted was {last}"
+                );
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("datagram {datagram_id} is out of order, last accepted was {last}"),
+                ));
+            }
+        }
+        self.last_datagram_id = Some(datagram_id);
+        Ok(())
+    }
+}
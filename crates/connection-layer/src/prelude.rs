@@ -9,6 +9,13 @@
 //! needed in user code.
 pub use crate::{
     client_codec::ConnectionLayerClientCodec,
-    host_codec::{ConnectionLayerHostCodec, DatagramHostDecoder, DatagramHostEncoder},
-    ConnectionId, ConnectionLayer, ConnectionLayerMode, RequestId,
+    header::{ConnectionHeaderWriter, HEADER_SIZE},
+    host_codec::{
+        ConnectionLayerHostCodec, DatagramHostDecoder, DatagramHostEncoder, DEFAULT_IDLE_TIMEOUT_MS,
+    },
+    parser::ConnectionLayerParser,
+    pipeline::DatagramReceivePipeline,
+    verify_hash, verify_hash_with, write_to_stream, write_to_stream_with, ChecksumAlgorithm,
+    ConnectionId, ConnectionLayer, ConnectionLayerError, ConnectionLayerMode, ConnectionSecretSeed,
+    Murmur3, RequestId,
 };
@@ -0,0 +1,68 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+//! Uses its own logger, so it runs in a separate test binary from the `test_log` tests.
+
+use connection_layer::prelude::*;
+use datagram::DatagramParser;
+use flood_rs::prelude::*;
+use log::kv::Key;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::sync::Mutex;
+
+struct CapturedRecord {
+    level: Level,
+    message: String,
+    connection_id: Option<String>,
+}
+
+struct CapturingLogger {
+    records: Mutex<Vec<CapturedRecord>>,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.records.lock().unwrap().push(CapturedRecord {
+            level: record.level(),
+            message: record.args().to_string(),
+            connection_id: record
+                .key_values()
+                .get(Key::from("connection_id"))
+                .map(|value| value.to_string()),
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger {
+    records: Mutex::new(Vec::new()),
+};
+
+#[test]
+fn hash_mismatch_is_logged_with_connection_id() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    let connection_id = ConnectionId { value: 42 };
+    let seed = ConnectionSecretSeed(0x12345678);
+    let mut writer = OutOctetStream::new();
+    write_to_stream(&mut writer, connection_id, seed, &[0x18, 0x28]).unwrap();
+    writer.write(&[0x18, 0x29]).unwrap();
+
+    let mut parser = ConnectionLayerParser::new(connection_id, seed);
+    assert!(parser.parse(writer.octets_ref()).is_err());
+
+    let records = LOGGER.records.lock().unwrap();
+    let mismatch = records
+        .iter()
+        .find(|record| record.message.starts_with("hash mismatch"))
+        .expect("hash mismatch should be logged");
+    assert_eq!(mismatch.level, Level::Warn);
+    assert_eq!(mismatch.connection_id.as_deref(), Some("42"));
+}
@@ -4,8 +4,10 @@
  */
 
 use connection_layer::prelude::*;
-use datagram::{DatagramDecoder, DatagramEncoder};
+use connection_layer::{FromOctets, ToOctets};
+use datagram::{DatagramDecoder, DatagramEncoder, DatagramParser};
 use flood_rs::prelude::*;
+use monotonic_time_rs::Millis;
 use secure_random::SecureRandom;
 use std::io;
 
@@ -20,8 +22,8 @@ fn test_header() {
     connection.to_stream(&mut writer).expect("should work");
 
     let buf = writer.octets_ref();
-    assert_eq!(buf[0], 42);
-    assert_eq!(&buf[1..=4], &[0xfe, 0x33, 0x44, 0x11]);
+    assert_eq!(&buf[0..2], &[0, 42]);
+    assert_eq!(&buf[2..HEADER_SIZE], &[0xfe, 0x33, 0x44, 0x11]);
 
     let mut reader = InOctetStream::new(buf);
     assert_eq!(
@@ -59,7 +61,7 @@ fn codec() -> io::Result<()> {
     // Verify
     #[rustfmt::skip]
     let expected_test_octets = &[
-        0, // Connection ID. Zero is OOB
+        0x00, 0x00, // Connection ID. Zero is OOB
         0x05, // Connect Request
         0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, // Request ID
         0x00, 0x02, // Connection Layer Version
@@ -69,7 +71,7 @@ fn codec() -> io::Result<()> {
     assert_eq!(decoded, test_octets);
 
     // Host -> Client
-    const EXPECTED_CONNECTION_ID: u8 = 1;
+    const EXPECTED_CONNECTION_ID: u16 = 1;
     assert_eq!(connection_id, EXPECTED_CONNECTION_ID);
 
     let test_reply_octets = &[b'w', b'o', b'r', b'l', b'd', b'!'];
@@ -77,10 +79,10 @@ fn codec() -> io::Result<()> {
     let host_to_client_reply = host_codec.encode(connection_id, test_reply_octets)?;
     #[rustfmt::skip]
     let expected_host_to_client_reply = &[
-        0, // Connection Id.
+        0x00, 0x00, // Connection Id.
         0x06, // Connect Response
         0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, // Request ID
-        EXPECTED_CONNECTION_ID, // Created connection id
+        0x00, 0x01, // Created connection id
         0x00, 0x00, 0x00, 0x01,  // Secret seed
         b'w', b'o', b'r', b'l', b'd', b'!'];
     hexify::assert_eq_slices(&host_to_client_reply, expected_host_to_client_reply);
@@ -95,7 +97,7 @@ fn codec() -> io::Result<()> {
     // verify
     #[rustfmt::skip]
     let expected_to_host_after_connected = &[
-        EXPECTED_CONNECTION_ID, // client should be connected now, so start using the client connection ID
+        0x00, 0x01, // client should be connected now, so start using the client connection ID
         19, 215, 173, 162,  // Hash for this content
         b'l', b'o', b'v', b'e', b'l', b'y'];
     hexify::assert_eq_slices(&to_host_after_connected, expected_to_host_after_connected);
@@ -112,7 +114,7 @@ fn codec() -> io::Result<()> {
     // verify
     #[rustfmt::skip]
     let expected_to_client_after_connected = &[
-        EXPECTED_CONNECTION_ID, // host should know that client is connected, so it will use connection_id without any command
+        0x00, 0x01, // host should know that client is connected, so it will use connection_id without any command
         129, 15, 178, 151, // Hash for this content
         b'w', b'o', b'r', b'k', b's'];
     hexify::assert_eq_slices(
@@ -122,3 +124,408 @@ fn codec() -> io::Result<()> {
 
     Ok(())
 }
+
+#[test_log::test]
+fn parser() -> io::Result<()> {
+    let connection_id = ConnectionId { value: 42 };
+    let seed = ConnectionSecretSeed(0x12345678);
+    let payload = &[0x18, 0x28, 0x38];
+
+    let mut writer = OutOctetStream::new();
+    write_to_stream(&mut writer, connection_id, seed, payload)?;
+    flood_rs::WriteOctetStream::write(&mut writer, payload)?;
+    let mut datagram = writer.octets();
+
+    let mut parser = ConnectionLayerParser::new(connection_id, seed);
+    assert_eq!(parser.parse(&datagram)?, payload);
+
+    let last = datagram.len() - 1;
+    datagram[last] ^= 0xff;
+    let err = parser.parse(&datagram).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    Ok(())
+}
+
+struct AdditiveChecksum;
+
+impl ChecksumAlgorithm for AdditiveChecksum {
+    fn compute(&self, payload: &[u8], seed: u32) -> u32 {
+        payload
+            .iter()
+            .fold(seed, |sum, octet| sum.wrapping_add(*octet as u32))
+    }
+}
+
+#[test_log::test]
+fn custom_checksum() -> io::Result<()> {
+    let connection_id = ConnectionId { value: 42 };
+    let seed = ConnectionSecretSeed(0x100);
+    let payload = &[0x01, 0x02, 0x03];
+
+    let mut writer = OutOctetStream::new();
+    write_to_stream_with(&AdditiveChecksum, &mut writer, connection_id, seed, payload)?;
+    hexify::assert_eq_slices(writer.octets_ref(), &[0, 42, 0x00, 0x00, 0x01, 0x06]);
+
+    let mut reader = InOctetStream::new(writer.octets_ref());
+    let ConnectionLayerMode::Connection(layer) = ConnectionLayerMode::from_stream(&mut reader)?
+    else {
+        panic!("expected a connection header");
+    };
+    verify_hash_with(&AdditiveChecksum, layer.murmur3_hash, seed, payload)?;
+    assert!(verify_hash(layer.murmur3_hash, seed, payload).is_err());
+
+    Ok(())
+}
+
+/// Pins the hash and header octets so that the wire format stays compatible with the C host.
+#[test_log::test]
+fn murmur3_golden_vector() {
+    // Reference vector for MurmurHash3_x86_32
+    assert_eq!(Murmur3.compute(b"hello", 0), 0x248bfa47);
+
+    let payload = [0x18, 0x28, 0x38, 0x48, 0x58];
+    let seed = ConnectionSecretSeed(0x12345678);
+    assert_eq!(Murmur3.compute(&payload, seed.0), 0x79898a47);
+
+    let mut writer = OutOctetStream::new();
+    write_to_stream(&mut writer, ConnectionId { value: 42 }, seed, &payload).unwrap();
+    assert_eq!(writer.octets_ref(), &[0, 42, 0x79, 0x89, 0x8a, 0x47]);
+
+    verify_hash(0x79898a47, seed, &payload).expect("golden hash should verify");
+}
+
+#[test_log::test]
+fn header_writer_reserve_and_finalize() {
+    let connection_id = ConnectionId { value: 42 };
+    let seed = ConnectionSecretSeed(0x12345678);
+    let payload = [0x18, 0x28, 0x38, 0x48, 0x58];
+
+    let mut writer = ConnectionHeaderWriter::new();
+    let mut stream = OutOctetStream::new();
+    writer.reserve(&mut stream).unwrap();
+    stream.write(&payload).unwrap();
+
+    let mut buf = stream.octets();
+    writer.finalize(&mut buf, connection_id, seed).unwrap();
+
+    let mut reader = InOctetStream::new(&buf);
+    let ConnectionLayerMode::Connection(header) =
+        ConnectionLayerMode::from_stream(&mut reader).unwrap()
+    else {
+        panic!("finalized header should be a connection header");
+    };
+    assert_eq!(header.connection_id, connection_id);
+    assert_eq!(&buf[HEADER_SIZE..], &payload);
+    verify_hash(header.murmur3_hash, seed, &buf[HEADER_SIZE..])
+        .expect("finalized header should verify");
+}
+
+#[test_log::test]
+fn connection_id_above_u8_range() -> io::Result<()> {
+    let connection_id = ConnectionId { value: 0x1234 };
+    let seed = ConnectionSecretSeed(0x12345678);
+    let payload = [0x18, 0x28, 0x38, 0x48, 0x58];
+
+    let mut writer = ConnectionHeaderWriter::new();
+    let mut stream = OutOctetStream::new();
+    writer.reserve(&mut stream)?;
+    stream.write(&payload)?;
+    let mut buf = stream.octets();
+    writer.finalize(&mut buf, connection_id, seed).unwrap();
+
+    hexify::assert_eq_slices(&buf[..HEADER_SIZE], &[0x12, 0x34, 0x79, 0x89, 0x8a, 0x47]);
+    let mut parser = ConnectionLayerParser::new(connection_id, seed);
+    assert_eq!(parser.parse(&buf)?, &payload);
+
+    Ok(())
+}
+
+#[test_log::test]
+fn host_assigns_connection_ids_above_255() -> io::Result<()> {
+    let mut host_codec = ConnectionLayerHostCodec::new(Box::new(FakeRandom { counter: 0 }));
+
+    let mut connection_id = 0;
+    for request_id in 0..300 {
+        let connect_request = ConnectionLayerClientCodec::new(request_id).encode(&[])?;
+        (connection_id, _) = host_codec.decode(&connect_request)?;
+    }
+    assert_eq!(connection_id, 300);
+
+    let mut client_codec = ConnectionLayerClientCodec::new(299);
+    client_codec.decode(&host_codec.encode(connection_id, &[])?)?;
+
+    let payload = &[0x18, 0x28, 0x38];
+    let (received_connection_id, received) = host_codec.decode(&client_codec.encode(payload)?)?;
+    assert_eq!(received_connection_id, connection_id);
+    assert_eq!(received, payload);
+
+    Ok(())
+}
+
+/// Runs the connect handshake for a new client and returns it with its connection id.
+fn connect_client(
+    host_codec: &mut ConnectionLayerHostCodec,
+    request_id: RequestId,
+) -> io::Result<(ConnectionLayerClientCodec, u16)> {
+    let mut client_codec = ConnectionLayerClientCodec::new(request_id);
+    let (connection_id, _) = host_codec.decode(&client_codec.encode(&[])?)?;
+    client_codec.decode(&host_codec.encode(connection_id, &[])?)?;
+    Ok((client_codec, connection_id))
+}
+
+#[test_log::test]
+fn host_evicts_idle_connections() -> io::Result<()> {
+    let mut host_codec = ConnectionLayerHostCodec::new(Box::new(FakeRandom { counter: 0 }));
+    host_codec.idle_timeout_ms = 1000;
+
+    assert!(host_codec.tick(Millis::new(0)).is_empty());
+    let (mut active_client, active_id) = connect_client(&mut host_codec, 1)?;
+    let (mut idle_client, idle_id) = connect_client(&mut host_codec, 2)?;
+
+    host_codec.tick(Millis::new(800));
+    host_codec.decode(&active_client.encode(&[0x01])?)?;
+
+    assert!(host_codec.tick(Millis::new(1000)).is_empty());
+    assert_eq!(
+        host_codec.tick(Millis::new(1001)),
+        vec![ConnectionId { value: idle_id }]
+    );
+    assert!(host_codec.connections.contains_key(&active_id));
+    assert!(!host_codec.connections.contains_key(&idle_id));
+
+    let err = host_codec
+        .decode(&idle_client.encode(&[0x02])?)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    host_codec.decode(&active_client.encode(&[0x03])?)?;
+
+    Ok(())
+}
+
+#[test_log::test]
+fn header_writer_rejects_short_buffer() {
+    let writer = ConnectionHeaderWriter::new();
+    let mut buf = [0u8; HEADER_SIZE - 1];
+    let err = writer
+        .finalize(&mut buf, ConnectionId { value: 1 }, ConnectionSecretSeed(0))
+        .unwrap_err();
+    assert!(matches!(err, ConnectionLayerError::Truncated));
+}
+
+fn is_truncated(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::UnexpectedEof
+        && matches!(
+            err.get_ref()
+                .and_then(|inner| inner.downcast_ref::<ConnectionLayerError>()),
+            Some(ConnectionLayerError::Truncated)
+        )
+}
+
+#[test_log::test]
+fn short_header_is_truncated() -> io::Result<()> {
+    let connection_id = ConnectionId { value: 42 };
+    let seed = ConnectionSecretSeed(0x12345678);
+
+    let mut parser = ConnectionLayerParser::new(connection_id, seed);
+    assert!(is_truncated(&parser.parse(&[0x00]).unwrap_err()));
+    assert!(is_truncated(&parser.parse(&[0x00, 42, 0xfe]).unwrap_err()));
+
+    let mut host_codec = ConnectionLayerHostCodec::new(Box::new(FakeRandom { counter: 0 }));
+    assert!(is_truncated(&host_codec.decode(&[0x00]).unwrap_err()));
+
+    let mut client_codec = ConnectionLayerClientCodec::new(0x0001020304050607);
+    let (host_connection_id, _) = host_codec.decode(&client_codec.encode(&[])?)?;
+    client_codec.decode(&host_codec.encode(host_connection_id, &[])?)?;
+    let short = [0x00, host_connection_id as u8, 0xfe, 0x33];
+    assert!(is_truncated(&host_codec.decode(&short).unwrap_err()));
+    assert!(is_truncated(&client_codec.decode(&short).unwrap_err()));
+
+    Ok(())
+}
+
+#[test_log::test]
+fn corrupted_payload_is_hash_mismatch() {
+    let seed = ConnectionSecretSeed(0x12345678);
+    let mut payload = [0x18, 0x28, 0x38, 0x48, 0x58];
+    payload[2] ^= 0x01;
+
+    let err = verify_hash(0x79898a47, seed, &payload).unwrap_err();
+    let ConnectionLayerError::HashMismatch {
+        expected,
+        calculated,
+    } = err
+    else {
+        panic!("corrupted payload should be a hash mismatch, got {err}");
+    };
+    assert_eq!(expected, 0x79898a47);
+    assert_eq!(calculated, Murmur3.compute(&payload, seed.0));
+
+    let io_err: io::Error = err.into();
+    assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test_log::test]
+fn host_rejects_implausible_version() {
+    let mut host_codec = ConnectionLayerHostCodec::new(Box::new(FakeRandom { counter: 0 }));
+
+    #[rustfmt::skip]
+    let connect_request = [
+        0x00, 0x00, // OOB connection id
+        0x05, // Connect command
+        0, 0, 0, 0, 0, 0, 0, 1, // Request id
+        0xff, 0xff, // Version
+    ];
+    let err = host_codec.decode(&connect_request).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(matches!(
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<ConnectionLayerError>()),
+        Some(ConnectionLayerError::ImplausibleVersion {
+            major: 0xff,
+            minor: 0xff
+        })
+    ));
+    assert!(host_codec.connections.is_empty());
+}
+
+/// Pins the big-endian (network order) layout of every integer width in the connection
+/// layer, so that a change in flood-rs can't silently break compatibility with the C host.
+#[test_log::test]
+fn integers_are_big_endian() -> io::Result<()> {
+    let connection = ConnectionLayerMode::Connection(ConnectionLayer {
+        connection_id: ConnectionId { value: 0x0102 },
+        murmur3_hash: 0x03040506,
+    });
+    let mut writer = OutOctetStream::new();
+    connection.to_stream(&mut writer)?;
+    #[rustfmt::skip]
+    let expected_header = [
+        0x01, 0x02, // Connection ID
+        0x03, 0x04, 0x05, 0x06, // Hash
+    ];
+    assert_eq!(writer.octets_ref(), &expected_header);
+
+    let mut host_codec = ConnectionLayerHostCodec::new(Box::new(FakeRandom {
+        counter: 0x0a0b0c0c,
+    }));
+    let mut client_codec = ConnectionLayerClientCodec::new(0x0102030405060708);
+    let (connection_id, _) = host_codec.decode(&client_codec.encode(&[])?)?;
+    #[rustfmt::skip]
+    let expected_connect_response = [
+        0x00, 0x00, // OOB connection id
+        0x06, // Connect Response
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // Request ID
+        0x00, 0x01, // Connection ID
+        0x0a, 0x0b, 0x0c, 0x0d, // Secret seed
+    ];
+    hexify::assert_eq_slices(
+        &host_codec.encode(connection_id, &[])?,
+        &expected_connect_response,
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct StepCommand(u8);
+
+impl Deserialize for StepCommand {
+    fn deserialize(stream: &mut impl ReadOctetStream) -> io::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self(stream.read_u8()?))
+    }
+}
+
+fn pipeline_datagram(
+    connection_id: ConnectionId,
+    seed: ConnectionSecretSeed,
+    datagram_id: u16,
+    commands: &[u8],
+) -> io::Result<Vec<u8>> {
+    let mut payload = datagram_id.to_be_bytes().to_vec();
+    payload.extend_from_slice(commands);
+
+    let mut writer = OutOctetStream::new();
+    write_to_stream(&mut writer, connection_id, seed, &payload)?;
+    flood_rs::WriteOctetStream::write(&mut writer, &payload)?;
+    Ok(writer.octets())
+}
+
+#[test_log::test]
+fn receive_pipeline_hands_commands_to_callback() -> io::Result<()> {
+    let connection_id = ConnectionId { value: 42 };
+    let seed = ConnectionSecretSeed(0x12345678);
+    let mut pipeline = DatagramReceivePipeline::new(connection_id, seed);
+
+    let datagram = pipeline_datagram(connection_id, seed, 7, &[0x18, 0x28, 0x38])?;
+    let mut commands = Vec::new();
+    pipeline.receive(&datagram, |command: StepCommand| commands.push(command))?;
+    assert_eq!(
+        commands,
+        [StepCommand(0x18), StepCommand(0x28), StepCommand(0x38)]
+    );
+
+    let err = pipeline
+        .receive(&datagram, |_: StepCommand| panic!("duplicate datagram"))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let older = pipeline_datagram(connection_id, seed, 6, &[0x48])?;
+    assert!(pipeline
+        .receive(&older, |_: StepCommand| panic!("older datagram"))
+        .is_err());
+
+    let mut corrupted = pipeline_datagram(connection_id, seed, 8, &[0x48])?;
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    assert!(pipeline
+        .receive(&corrupted, |_: StepCommand| panic!("corrupted datagram"))
+        .is_err());
+
+    let newer = pipeline_datagram(connection_id, seed, 8, &[0x48])?;
+    commands.clear();
+    pipeline.receive(&newer, |command: StepCommand| commands.push(command))?;
+    assert_eq!(commands, [StepCommand(0x48)]);
+
+    Ok(())
+}
+
+#[test_log::test]
+fn receive_pipeline_accepts_wrapped_datagram_id() -> io::Result<()> {
+    let connection_id = ConnectionId { value: 42 };
+    let seed = ConnectionSecretSeed(0x12345678);
+    let mut pipeline = DatagramReceivePipeline::new(connection_id, seed);
+
+    let last = pipeline_datagram(connection_id, seed, u16::MAX, &[])?;
+    pipeline.receive(&last, |_: StepCommand| {})?;
+
+    let wrapped = pipeline_datagram(connection_id, seed, 0, &[0x01])?;
+    let mut commands = Vec::new();
+    pipeline.receive(&wrapped, |command: StepCommand| commands.push(command))?;
+    assert_eq!(commands, [StepCommand(0x01)]);
+
+    Ok(())
+}
+
+#[test_log::test]
+fn connection_id_uses_octet_helpers() -> io::Result<()> {
+    let connection_id = ConnectionId { value: 0x0102 };
+    assert_eq!(connection_id.to_octets()?, [0x01, 0x02]);
+    assert_eq!(ConnectionId::from_octets(&[0x01, 0x02])?, connection_id);
+
+    let err = ConnectionId::from_octets(&[0x01, 0x02, 0xff]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let header = ConnectionLayerMode::Connection(ConnectionLayer {
+        connection_id,
+        murmur3_hash: 0xfe334411,
+    });
+    let octets = header.to_octets()?;
+    assert_eq!(octets, [0x01, 0x02, 0xfe, 0x33, 0x44, 0x11]);
+    assert_eq!(ConnectionLayerMode::from_octets(&octets)?, header);
+
+    Ok(())
+}
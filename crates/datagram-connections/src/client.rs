@@ -1,8 +1,10 @@
 use crate::host_to_client::HostToClientCommands;
+use crate::stream::ensure_remaining;
 use crate::{
-    ClientPhase, ClientToHostChallengeCommand, ClientToHostCommands, ClientToHostPacket,
-    ConnectCommand, ConnectResponse, DatagramConnectionsError, HostToClientPacketHeader,
-    InChallengeCommand, Nonce, PacketHeader,
+    ChallengeRequestId, ClientEvent, ClientPhase, ClientToHostChallengeCommand,
+    ClientToHostCommands, ClientToHostPacket, ConnectCommand, ConnectResponse,
+    DatagramConnectionsError, DisconnectAck, DisconnectCommand, DisconnectReason,
+    HostToClientPacketHeader, InChallengeCommand, Nonce,
 };
 use datagram::{DatagramDecoder, DatagramEncoder};
 use flood_rs::in_stream::InOctetStream;
@@ -20,6 +22,7 @@ impl DatagramEncoder for Client {
         let client_to_server_cmd = self
             .send(data)
             .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        trace!("sending command {}", client_to_server_cmd);
 
         client_to_server_cmd.to_stream(&mut out_stream)?;
         out_stream.write(data)?;
@@ -37,12 +40,87 @@ impl DatagramDecoder for Client {
 
 pub struct Client {
     phase: ClientPhase,
+    random: Box<dyn SecureRandom>,
+    events: Vec<ClientEvent>,
 }
 
 impl Client {
-    pub fn new(mut random: Box<dyn SecureRandom>) -> Self {
-        let phase = ClientPhase::Challenge(Nonce(random.random_u64()));
-        Self { phase }
+    pub fn new(random: Box<dyn SecureRandom>) -> Self {
+        let mut client = Self {
+            phase: ClientPhase::Disconnected,
+            random,
+            events: Vec::new(),
+        };
+        client.start_challenge();
+        client
+    }
+
+    pub fn phase(&self) -> &ClientPhase {
+        &self.phase
+    }
+
+    /// Returns the connection events that happened since the last call.
+    pub fn poll_events(&mut self) -> Vec<ClientEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Starts a graceful disconnect.
+    ///
+    /// A connected client keeps sending [`DisconnectCommand`] until the host replies with a
+    /// [`DisconnectAck`]. A client that never connected has nothing to tell the host and is
+    /// disconnected immediately.
+    pub fn disconnect(&mut self) {
+        self.phase = match self.phase {
+            ClientPhase::Connected(connection_id) | ClientPhase::Disconnecting(connection_id) => {
+                ClientPhase::Disconnecting(connection_id)
+            }
+            _ => ClientPhase::Disconnected,
+        };
+    }
+
+    /// Ends the connection without waiting for the host, for a caller that has not heard
+    /// from the host for too long.
+    ///
+    /// A connected or disconnecting client becomes disconnected and reports
+    /// [`DisconnectReason::TimedOut`]. The handshake phases have no connection to end, so
+    /// the client keeps retrying them.
+    pub fn time_out(&mut self) {
+        if let ClientPhase::Connected(connection_id) | ClientPhase::Disconnecting(connection_id) =
+            self.phase
+        {
+            info!(connection_id = connection_id.0; "udp_connections: timed out {}", connection_id);
+            self.phase = ClientPhase::Disconnected;
+            self.events.push(ClientEvent::Disconnected {
+                connection_id,
+                reason: DisconnectReason::TimedOut,
+            });
+        }
+    }
+
+    /// Starts a new connection attempt once a disconnect has completed.
+    ///
+    /// Does nothing while the client is connecting, connected or disconnecting.
+    pub fn connect(&mut self) {
+        if self.phase == ClientPhase::Disconnected {
+            self.start_challenge();
+        }
+    }
+
+    /// Starts a new challenge attempt with a freshly generated nonce and [`ChallengeRequestId`].
+    ///
+    /// Only called by [`Client::new`] and [`Client::connect`], so a connected client can't
+    /// be knocked back into the challenge phase. The host must echo both in the
+    /// [`InChallengeCommand`], and a response from an earlier attempt is rejected, so a
+    /// captured challenge can not be replayed into a later attempt.
+    fn start_challenge(&mut self) {
+        let nonce = Nonce(self.random.random_u64());
+        let request_id = ChallengeRequestId(self.random.random_u64());
+        trace!(
+            "starting new challenge attempt with {} {}",
+            nonce,
+            request_id
+        );
+        self.phase = ClientPhase::Challenge(nonce, request_id);
     }
 
     pub fn on_challenge(
@@ -50,10 +128,13 @@ impl Client {
         cmd: InChallengeCommand,
     ) -> Result<(), DatagramConnectionsError> {
         match self.phase {
-            ClientPhase::Challenge(nonce) => {
+            ClientPhase::Challenge(nonce, request_id) => {
                 if cmd.nonce != nonce {
                     return Err(DatagramConnectionsError::WrongNonceInChallenge);
                 }
+                if cmd.request_id != request_id {
+                    return Err(DatagramConnectionsError::WrongRequestIdInChallenge);
+                }
                 self.phase = ClientPhase::Connecting(nonce, cmd.incoming_server_challenge);
                 Ok(())
             }
@@ -68,10 +149,14 @@ impl Client {
                     return Err(DatagramConnectionsError::WrongNonceWhileConnecting);
                 }
                 info!(
+                    connection_id = cmd.connection_id.0;
                     "udp_connections: on_connect connected {}",
                     cmd.connection_id
                 );
                 self.phase = ClientPhase::Connected(cmd.connection_id);
+                self.events.push(ClientEvent::Connected {
+                    connection_id: cmd.connection_id,
+                });
                 Ok(())
             }
             _ => Err(DatagramConnectionsError::ReceiveConnectInWrongPhase),
@@ -88,11 +173,20 @@ impl Client {
                 if cmd.0.connection_id != expected_connection_id {
                     return Err(DatagramConnectionsError::WrongConnectionId);
                 }
+                // A short packet only fails this datagram. The connection stays up, so the
+                // caller can log the error and continue with the next datagram.
+                ensure_remaining(
+                    in_stream,
+                    cmd.0.size as usize,
+                    "HostToClient packet payload",
+                )
+                .map_err(DatagramConnectionsError::IoError)?;
                 let mut target_buffer = vec![0u8; cmd.0.size as usize];
                 in_stream
                     .read(&mut target_buffer)
                     .map_err(DatagramConnectionsError::IoError)?;
                 trace!(
+                    connection_id = expected_connection_id.0;
                     "receive packet of size: {} target:{}  {}",
                     cmd.0.size,
                     target_buffer.len(),
@@ -104,11 +198,34 @@ impl Client {
         }
     }
 
+    pub fn on_disconnect_ack(
+        &mut self,
+        cmd: DisconnectAck,
+    ) -> Result<(), DatagramConnectionsError> {
+        match self.phase {
+            ClientPhase::Disconnecting(connection_id) => {
+                if cmd.connection_id != connection_id {
+                    return Err(DatagramConnectionsError::WrongConnectionId);
+                }
+                info!(connection_id = connection_id.0; "udp_connections: disconnected {}", connection_id);
+                self.phase = ClientPhase::Disconnected;
+                self.events.push(ClientEvent::Disconnected {
+                    connection_id,
+                    reason: DisconnectReason::Acknowledged,
+                });
+                Ok(())
+            }
+            _ => Err(DatagramConnectionsError::ReceivedDisconnectAckInWrongPhase),
+        }
+    }
+
     pub fn send_challenge(
         &mut self,
     ) -> Result<ClientToHostChallengeCommand, DatagramConnectionsError> {
         match self.phase {
-            ClientPhase::Challenge(nonce) => Ok(ClientToHostChallengeCommand { nonce }),
+            ClientPhase::Challenge(nonce, request_id) => {
+                Ok(ClientToHostChallengeCommand { nonce, request_id })
+            }
             _ => Err(DatagramConnectionsError::SendChallengeInWrongPhase),
         }
     }
@@ -117,7 +234,7 @@ impl Client {
         match self.phase {
             ClientPhase::Connecting(nonce, server_challenge) => Ok(ConnectCommand {
                 nonce,
-                server_challenge,
+                challenge_proof: server_challenge.proof(nonce),
             }),
             _ => Err(DatagramConnectionsError::SendConnectRequestInWrongPhase),
         }
@@ -129,14 +246,8 @@ impl Client {
     ) -> Result<ClientToHostPacket, DatagramConnectionsError> {
         match self.phase {
             ClientPhase::Connected(connection_id) => {
-                trace!("send packet: {}", format_hex(data));
-                Ok(ClientToHostPacket {
-                    header: PacketHeader {
-                        connection_id,
-                        size: data.len() as u16,
-                    },
-                    payload: data.to_vec(),
-                })
+                trace!(connection_id = connection_id.0; "send packet: {}", format_hex(data));
+                ClientToHostPacket::new(connection_id, data)
             }
             _ => Err(DatagramConnectionsError::SendPacketInWrongPhase),
         }
@@ -145,7 +256,7 @@ impl Client {
     pub fn send(&mut self, data: &[u8]) -> Result<ClientToHostCommands, DatagramConnectionsError> {
         trace!("send: phase: {}", self.phase);
         match self.phase {
-            ClientPhase::Challenge(_) => {
+            ClientPhase::Challenge(..) => {
                 let challenge = self.send_challenge()?;
                 Ok(ClientToHostCommands::ChallengeType(challenge))
             }
@@ -157,16 +268,24 @@ impl Client {
 
             ClientPhase::Connected(_) => {
                 let packet = self.send_packet(data)?;
-                trace!("sending datagram {:?}", packet);
                 Ok(ClientToHostCommands::PacketType(packet))
             }
+
+            ClientPhase::Disconnecting(connection_id) => {
+                Ok(ClientToHostCommands::DisconnectType(DisconnectCommand {
+                    connection_id,
+                }))
+            }
+
+            ClientPhase::Disconnected => Err(DatagramConnectionsError::SendAfterDisconnected),
         }
     }
 
     pub fn decode(&mut self, buffer: &[u8]) -> Result<Vec<u8>, DatagramConnectionsError> {
         let mut in_stream = InOctetStream::new(buffer);
-        let command = HostToClientCommands::from_stream(&mut in_stream)
+        let command = HostToClientCommands::from_in_stream(&mut in_stream)
             .map_err(DatagramConnectionsError::IoError)?;
+        trace!("received command {}", command);
 
         match command {
             HostToClientCommands::ChallengeType(challenge_command) => {
@@ -180,6 +299,10 @@ impl Client {
             HostToClientCommands::PacketType(packet_command) => {
                 self.on_packet(packet_command, &mut in_stream)
             }
+            HostToClientCommands::DisconnectAckType(disconnect_ack) => {
+                self.on_disconnect_ack(disconnect_ack)?;
+                Ok(vec![])
+            }
         }
     }
 }
@@ -1,12 +1,16 @@
-use crate::{ClientToHostChallengeCommand, ClientToHostCommands};
+use crate::{
+    ClientToHostChallengeCommand, ClientToHostCommands, ConnectCommand, DisconnectCommand,
+};
 use flood_rs::{ReadOctetStream, WriteOctetStream};
-use std::io;
+use std::fmt::{Display, Formatter};
+use std::{fmt, io};
 
 #[repr(u8)]
 pub enum ClientToHostCommand {
     Challenge = 0x01,
     Connect = 0x02,
     Packet = 0x03,
+    Disconnect = 0x04,
 }
 
 // Implement TryFrom to convert u8 to Command
@@ -18,6 +22,7 @@ impl TryFrom<u8> for ClientToHostCommand {
             0x01 => Ok(ClientToHostCommand::Challenge),
             0x02 => Ok(ClientToHostCommand::Connect),
             0x03 => Ok(ClientToHostCommand::Packet),
+            0x04 => Ok(ClientToHostCommand::Disconnect),
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 format!("Unknown command {}", value),
@@ -32,6 +37,7 @@ impl ClientToHostCommands {
             ClientToHostCommands::ChallengeType(_) => ClientToHostCommand::Challenge,
             ClientToHostCommands::ConnectType(_) => ClientToHostCommand::Connect,
             ClientToHostCommands::PacketType(_) => ClientToHostCommand::Packet,
+            ClientToHostCommands::DisconnectType(_) => ClientToHostCommand::Disconnect,
         }
     }
 
@@ -45,6 +51,9 @@ impl ClientToHostCommands {
             ClientToHostCommands::PacketType(client_to_host_packet) => {
                 client_to_host_packet.to_stream(stream)
             }
+            ClientToHostCommands::DisconnectType(disconnect_command) => {
+                disconnect_command.to_stream(stream)
+            }
         }
     }
 
@@ -55,6 +64,12 @@ impl ClientToHostCommands {
             ClientToHostCommand::Challenge => ClientToHostCommands::ChallengeType(
                 ClientToHostChallengeCommand::from_stream(stream)?,
             ),
+            ClientToHostCommand::Connect => {
+                ClientToHostCommands::ConnectType(ConnectCommand::from_stream(stream)?)
+            }
+            ClientToHostCommand::Disconnect => {
+                ClientToHostCommands::DisconnectType(DisconnectCommand::from_stream(stream)?)
+            }
             _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
@@ -65,3 +80,28 @@ impl ClientToHostCommands {
         Ok(x)
     }
 }
+
+impl Display for ClientToHostCommands {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ChallengeType(challenge) => {
+                write!(
+                    f,
+                    "Challenge({}, {})",
+                    challenge.nonce, challenge.request_id
+                )
+            }
+            Self::ConnectType(connect) => {
+                write!(f, "Connect({}, {})", connect.nonce, connect.challenge_proof)
+            }
+            Self::PacketType(packet) => write!(
+                f,
+                "Packet({}, {} octets)",
+                packet.header.connection_id, packet.header.size
+            ),
+            Self::DisconnectType(disconnect) => {
+                write!(f, "Disconnect({})", disconnect.connection_id)
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use crate::{
+    keyed_hash, ClientToHostChallengeCommand, ConnectCommand, ConnectResponse, ConnectionId,
+    DatagramConnectionsError, InChallengeCommand, Nonce, ServerChallenge,
+};
+use log::{info, trace};
+use secure_random::SecureRandom;
+
+/// The host side of the challenge and connect handshake.
+///
+/// The [`ServerChallenge`] for a client is a keyed hash of its nonce with a secret that
+/// is drawn when the handshake is created, so the host doesn't need to remember the
+/// challenges it has handed out. A [`ConnectCommand`] is only accepted if it carries the
+/// [`ServerChallenge::proof`] of the challenge for its nonce.
+pub struct HostHandshake {
+    secret: u64,
+    random: Box<dyn SecureRandom>,
+}
+
+impl HostHandshake {
+    pub fn new(mut random: Box<dyn SecureRandom>) -> Self {
+        Self {
+            secret: random.random_u64(),
+            random,
+        }
+    }
+
+    fn challenge_for(&self, nonce: Nonce) -> ServerChallenge {
+        ServerChallenge(keyed_hash(&nonce.0.to_be_bytes(), self.secret))
+    }
+
+    /// Answers a challenge request with the [`ServerChallenge`] for its nonce.
+    pub fn on_challenge(&self, cmd: &ClientToHostChallengeCommand) -> InChallengeCommand {
+        let challenge = self.challenge_for(cmd.nonce);
+        trace!("challenge for {}: {}", cmd.nonce, challenge);
+        InChallengeCommand {
+            nonce: cmd.nonce,
+            request_id: cmd.request_id,
+            incoming_server_challenge: challenge,
+        }
+    }
+
+    /// Accepts a connect request and assigns it a new [`ConnectionId`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DatagramConnectionsError::WrongChallengeProof`] if the proof is not the one
+    /// for the challenge that was sent for the nonce of the request.
+    pub fn on_connect(
+        &mut self,
+        cmd: &ConnectCommand,
+    ) -> Result<ConnectResponse, DatagramConnectionsError> {
+        if cmd.challenge_proof != self.challenge_for(cmd.nonce).proof(cmd.nonce) {
+            return Err(DatagramConnectionsError::WrongChallengeProof);
+        }
+        let connection_id = ConnectionId(self.random.random_u64());
+        info!(connection_id = connection_id.0; "udp_connections: accepted {} as {}", cmd.nonce, connection_id);
+        Ok(ConnectResponse {
+            nonce: cmd.nonce,
+            connection_id,
+        })
+    }
+}
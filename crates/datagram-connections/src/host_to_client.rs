@@ -1,12 +1,19 @@
-use crate::{ConnectResponse, HostToClientPacketHeader, InChallengeCommand};
+use crate::stream::{ensure_remaining, name_truncated, remaining};
+use crate::{
+    ConnectResponse, DisconnectAck, HostToClientPacketHeader, InChallengeCommand, PacketHeader,
+    ProtocolError,
+};
+use flood_rs::in_stream::InOctetStream;
 use flood_rs::{ReadOctetStream, WriteOctetStream};
-use std::io;
+use std::fmt::{Display, Formatter};
+use std::{fmt, io};
 
 #[repr(u8)]
 pub enum HostToClientCommand {
     Challenge = 0x11,
     Connect = 0x12,
     Packet = 0x13,
+    DisconnectAck = 0x14,
 }
 
 impl TryFrom<u8> for HostToClientCommand {
@@ -17,6 +24,7 @@ impl TryFrom<u8> for HostToClientCommand {
             0x11 => Ok(Self::Challenge),
             0x12 => Ok(Self::Connect),
             0x13 => Ok(Self::Packet),
+            0x14 => Ok(Self::DisconnectAck),
             _ => Err(io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 format!("Unknown HostToClient DatagramConnections Command {}", value),
@@ -25,11 +33,33 @@ impl TryFrom<u8> for HostToClientCommand {
     }
 }
 
+impl HostToClientCommand {
+    /// Size of the command body on the wire, in octets, not counting the command octet.
+    pub const fn body_size(&self) -> usize {
+        match self {
+            Self::Challenge => InChallengeCommand::SIZE,
+            Self::Connect => ConnectResponse::SIZE,
+            Self::Packet => PacketHeader::SIZE,
+            Self::DisconnectAck => DisconnectAck::SIZE,
+        }
+    }
+
+    const fn body_name(&self) -> &'static str {
+        match self {
+            Self::Challenge => "InChallengeCommand",
+            Self::Connect => "ConnectResponse",
+            Self::Packet => "PacketHeader",
+            Self::DisconnectAck => "DisconnectAck",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum HostToClientCommands {
     ChallengeType(InChallengeCommand),
     ConnectType(ConnectResponse),
     PacketType(HostToClientPacketHeader),
+    DisconnectAckType(DisconnectAck),
 }
 
 impl HostToClientCommands {
@@ -39,6 +69,7 @@ impl HostToClientCommands {
             Self::ChallengeType(_) => HostToClientCommand::Challenge,
             Self::ConnectType(_) => HostToClientCommand::Connect,
             Self::PacketType(_) => HostToClientCommand::Packet,
+            Self::DisconnectAckType(_) => HostToClientCommand::DisconnectAck,
         }
     }
 
@@ -51,12 +82,51 @@ impl HostToClientCommands {
             }
             Self::ConnectType(connect_command) => connect_command.to_stream(stream),
             Self::PacketType(client_to_host_packet) => client_to_host_packet.0.to_stream(stream),
+            Self::DisconnectAckType(disconnect_ack) => disconnect_ack.to_stream(stream),
         }
     }
 
+    /// Reads a command from any stream. A truncated command is reported as an
+    /// [`io::ErrorKind::UnexpectedEof`] error naming the part that was cut short.
     pub fn from_stream(stream: &mut impl ReadOctetStream) -> io::Result<Self> {
+        let command_value = name_truncated(stream.read_u8(), "HostToClient command")?;
+        let command = HostToClientCommand::try_from(command_value)?;
+        let body_name = command.body_name();
+        name_truncated(Self::body_from_stream(command, stream), body_name)
+    }
+
+    /// Like [`HostToClientCommands::from_stream`], but checks the remaining octets before
+    /// reading, so the error also tells how many octets were expected and available.
+    pub(crate) fn from_in_stream(stream: &mut InOctetStream) -> io::Result<Self> {
+        ensure_remaining(stream, 1, "HostToClient command")?;
         let command_value = stream.read_u8()?;
         let command = HostToClientCommand::try_from(command_value)?;
+        ensure_remaining(stream, command.body_size(), command.body_name())?;
+        Self::body_from_stream(command, stream)
+    }
+
+    /// Like [`HostToClientCommands::from_stream`], but reports malformed datagrams as a
+    /// typed [`ProtocolError`].
+    ///
+    /// The body length is checked against [`HostToClientCommand::body_size`] before
+    /// anything is read, so a truncated command never reaches the body parsers.
+    pub fn try_from_stream(stream: &mut InOctetStream) -> Result<Self, ProtocolError> {
+        if remaining(stream) < 1 {
+            return Err(ProtocolError::MissingCommand);
+        }
+        let command_id = stream.read_u8().map_err(ProtocolError::IoError)?;
+        let command = HostToClientCommand::try_from(command_id)
+            .map_err(|_| ProtocolError::UnknownCommand(command_id))?;
+        if remaining(stream) < command.body_size() {
+            return Err(ProtocolError::TruncatedCommand { command_id });
+        }
+        Self::body_from_stream(command, stream).map_err(ProtocolError::IoError)
+    }
+
+    fn body_from_stream(
+        command: HostToClientCommand,
+        stream: &mut impl ReadOctetStream,
+    ) -> io::Result<Self> {
         let x = match command {
             HostToClientCommand::Challenge => {
                 Self::ChallengeType(InChallengeCommand::from_stream(stream)?)
@@ -67,7 +137,33 @@ impl HostToClientCommands {
             HostToClientCommand::Packet => {
                 Self::PacketType(HostToClientPacketHeader::from_stream(stream)?)
             }
+            HostToClientCommand::DisconnectAck => {
+                Self::DisconnectAckType(DisconnectAck::from_stream(stream)?)
+            }
         };
         Ok(x)
     }
 }
+
+impl Display for HostToClientCommands {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ChallengeType(challenge) => write!(
+                f,
+                "Challenge({}, {}, {})",
+                challenge.nonce, challenge.request_id, challenge.incoming_server_challenge
+            ),
+            Self::ConnectType(connect) => {
+                write!(f, "Connect({}, {})", connect.nonce, connect.connection_id)
+            }
+            Self::PacketType(packet) => write!(
+                f,
+                "Packet({}, {} octets)",
+                packet.0.connection_id, packet.0.size
+            ),
+            Self::DisconnectAckType(disconnect_ack) => {
+                write!(f, "DisconnectAck({})", disconnect_ack.connection_id)
+            }
+        }
+    }
+}
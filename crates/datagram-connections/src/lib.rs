@@ -4,16 +4,24 @@
  */
 mod client;
 mod client_to_host;
+mod host;
 mod host_to_client;
+mod octets;
 pub mod prelude;
+pub mod stream;
 
 use flood_rs::prelude::*;
+use flood_rs::{Deserialize, Serialize};
 use log::info;
+use mash_rs::murmur3_32;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::{fmt, io};
+use stream::name_truncated;
 
-#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub use octets::{FromOctets, ToOctets};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct Nonce(pub u64);
 
 impl Nonce {
@@ -78,6 +86,19 @@ impl ServerChallenge {
         let x = stream.read_u64()?;
         Ok(Self(x))
     }
+
+    /// Computes the [`ChallengeProof`] that the client sends back for this challenge.
+    ///
+    /// The proof is a 64-bit keyed hash of the big-endian `nonce` octets, keyed with the
+    /// challenge: the high half is [`murmur3_32`] seeded with the high 32 bits of the
+    /// challenge, and the low half is [`murmur3_32`] seeded with the low 32 bits.
+    ///
+    /// It binds the challenge to the nonce of the connection attempt, so a client must
+    /// have received the challenge sent for its own nonce to connect. It is not a
+    /// cryptographic MAC and does not authenticate the client.
+    pub fn proof(&self, nonce: Nonce) -> ChallengeProof {
+        ChallengeProof(keyed_hash(&nonce.0.to_be_bytes(), self.0))
+    }
 }
 
 impl fmt::Display for ServerChallenge {
@@ -86,6 +107,66 @@ impl fmt::Display for ServerChallenge {
     }
 }
 
+/// The client's answer to a [`ServerChallenge`], see [`ServerChallenge::proof`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChallengeProof(pub u64);
+
+impl ChallengeProof {
+    pub fn new(value: u64) -> Self {
+        Self(value)
+    }
+
+    pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
+        stream.write_u64(self.0)?;
+        Ok(())
+    }
+
+    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+        let x = stream.read_u64()?;
+        Ok(Self(x))
+    }
+}
+
+impl fmt::Display for ChallengeProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChallengeProof({:X})", self.0)
+    }
+}
+
+/// Two [`murmur3_32`] hashes of `octets`, seeded with each half of `key`.
+fn keyed_hash(octets: &[u8], key: u64) -> u64 {
+    let high = murmur3_32(octets, (key >> 32) as u32);
+    let low = murmur3_32(octets, key as u32);
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// Identifies one challenge attempt. The host echoes it in the [`InChallengeCommand`], so a
+/// challenge response captured from an earlier attempt is rejected.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChallengeRequestId(pub u64);
+
+impl ChallengeRequestId {
+    pub fn new(value: u64) -> Self {
+        Self(value)
+    }
+
+    pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
+        stream.write_u64(self.0)?;
+        Ok(())
+    }
+
+    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+        let x = stream.read_u64()?;
+        Ok(Self(x))
+    }
+}
+
+impl fmt::Display for ChallengeRequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChallengeRequestId({:X})", self.0)
+    }
+}
+
 #[derive(Debug)]
 pub struct ClientToHostPacket {
     pub header: PacketHeader,
@@ -93,6 +174,27 @@ pub struct ClientToHostPacket {
 }
 
 impl ClientToHostPacket {
+    /// Creates a packet for `payload`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DatagramConnectionsError::PacketTooLarge`] if the payload does not fit in the
+    /// `u16` size of the [`PacketHeader`].
+    pub fn new(
+        connection_id: ConnectionId,
+        payload: &[u8],
+    ) -> Result<Self, DatagramConnectionsError> {
+        let size = u16::try_from(payload.len())
+            .map_err(|_| DatagramConnectionsError::PacketTooLarge(payload.len()))?;
+        Ok(Self {
+            header: PacketHeader {
+                connection_id,
+                size,
+            },
+            payload: payload.to_vec(),
+        })
+    }
+
     pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
         self.header.to_stream(stream)?;
         stream.write(self.payload.as_slice())?;
@@ -100,9 +202,12 @@ impl ClientToHostPacket {
     }
 
     pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
-        let header = PacketHeader::from_stream(stream)?;
-        let mut target_buffer = Vec::with_capacity(header.size as usize);
-        stream.read(&mut target_buffer)?;
+        let header = name_truncated(PacketHeader::from_stream(stream), "PacketHeader")?;
+        let mut target_buffer = vec![0u8; header.size as usize];
+        name_truncated(
+            stream.read(&mut target_buffer),
+            "ClientToHostPacket payload",
+        )?;
         Ok(Self {
             header,
             payload: target_buffer,
@@ -117,6 +222,9 @@ pub struct PacketHeader {
 }
 
 impl PacketHeader {
+    /// Size of the header on the wire, in octets.
+    pub const SIZE: usize = 8 + 2;
+
     pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
         self.connection_id.to_stream(stream)?;
         stream.write_u16(self.size)?;
@@ -144,20 +252,38 @@ impl HostToClientPacketHeader {
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct ConnectCommand {
     pub nonce: Nonce,
-    pub server_challenge: ServerChallenge,
+    pub challenge_proof: ChallengeProof,
 }
 
 impl ConnectCommand {
     pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
+        self.serialize(stream)
+    }
+
+    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+        Self::deserialize(stream)
+    }
+}
+
+impl Serialize for ConnectCommand {
+    fn serialize(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()>
+    where
+        Self: Sized,
+    {
         self.nonce.to_stream(stream)?;
-        self.server_challenge.to_stream(stream)?;
+        self.challenge_proof.to_stream(stream)?;
         Ok(())
     }
+}
 
-    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+impl Deserialize for ConnectCommand {
+    fn deserialize(stream: &mut impl ReadOctetStream) -> std::io::Result<Self>
+    where
+        Self: Sized,
+    {
         Ok(Self {
             nonce: Nonce::from_stream(stream)?,
-            server_challenge: ServerChallenge::from_stream(stream)?,
+            challenge_proof: ChallengeProof::from_stream(stream)?,
         })
     }
 }
@@ -165,20 +291,44 @@ impl ConnectCommand {
 #[derive(Debug, PartialEq)]
 pub struct InChallengeCommand {
     pub nonce: Nonce,
+    pub request_id: ChallengeRequestId,
     pub incoming_server_challenge: ServerChallenge,
 }
 
 impl InChallengeCommand {
+    /// Size of the command on the wire, in octets.
+    pub const SIZE: usize = 8 + 8 + 8;
+
     pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
+        self.serialize(stream)
+    }
+
+    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+        Self::deserialize(stream)
+    }
+}
+
+impl Serialize for InChallengeCommand {
+    fn serialize(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()>
+    where
+        Self: Sized,
+    {
         self.nonce.to_stream(stream)?;
+        self.request_id.to_stream(stream)?;
         self.incoming_server_challenge.to_stream(stream)?;
 
         Ok(())
     }
+}
 
-    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+impl Deserialize for InChallengeCommand {
+    fn deserialize(stream: &mut impl ReadOctetStream) -> std::io::Result<Self>
+    where
+        Self: Sized,
+    {
         Ok(Self {
             nonce: Nonce::from_stream(stream)?,
+            request_id: ChallengeRequestId::from_stream(stream)?,
             incoming_server_challenge: ServerChallenge::from_stream(stream)?,
         })
     }
@@ -187,17 +337,38 @@ impl InChallengeCommand {
 #[derive(Debug, PartialEq)]
 pub struct ClientToHostChallengeCommand {
     pub nonce: Nonce,
+    pub request_id: ChallengeRequestId,
 }
 
 impl ClientToHostChallengeCommand {
     pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
         self.nonce.to_stream(stream)?;
+        self.request_id.to_stream(stream)?;
         Ok(())
     }
 
     pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
         Ok(Self {
             nonce: Nonce::from_stream(stream)?,
+            request_id: ChallengeRequestId::from_stream(stream)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DisconnectCommand {
+    pub connection_id: ConnectionId,
+}
+
+impl DisconnectCommand {
+    pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
+        self.connection_id.to_stream(stream)?;
+        Ok(())
+    }
+
+    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+        Ok(Self {
+            connection_id: ConnectionId::from_stream(stream)?,
         })
     }
 }
@@ -207,6 +378,7 @@ pub enum ClientToHostCommands {
     ChallengeType(ClientToHostChallengeCommand),
     ConnectType(ConnectCommand),
     PacketType(ClientToHostPacket),
+    DisconnectType(DisconnectCommand),
 }
 
 #[derive(Debug, PartialEq)]
@@ -234,13 +406,34 @@ pub struct ConnectResponse {
 }
 
 impl ConnectResponse {
+    /// Size of the command on the wire, in octets.
+    pub const SIZE: usize = 8 + 8;
+
     pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
+        self.serialize(stream)
+    }
+
+    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+        Self::deserialize(stream)
+    }
+}
+
+impl Serialize for ConnectResponse {
+    fn serialize(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()>
+    where
+        Self: Sized,
+    {
         self.nonce.to_stream(stream)?;
         self.connection_id.to_stream(stream)?;
         Ok(())
     }
+}
 
-    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+impl Deserialize for ConnectResponse {
+    fn deserialize(stream: &mut impl ReadOctetStream) -> std::io::Result<Self>
+    where
+        Self: Sized,
+    {
         Ok(Self {
             nonce: Nonce::from_stream(stream)?,
             connection_id: ConnectionId::from_stream(stream)?,
@@ -248,18 +441,45 @@ impl ConnectResponse {
     }
 }
 
-#[derive(PartialEq, Debug)]
-enum ClientPhase {
-    Challenge(Nonce),
+#[derive(Debug, PartialEq)]
+pub struct DisconnectAck {
+    pub connection_id: ConnectionId,
+}
+
+impl DisconnectAck {
+    /// Size of the command on the wire, in octets.
+    pub const SIZE: usize = 8;
+
+    pub fn to_stream(&self, stream: &mut impl WriteOctetStream) -> std::io::Result<()> {
+        self.connection_id.to_stream(stream)?;
+        Ok(())
+    }
+
+    pub fn from_stream(stream: &mut impl ReadOctetStream) -> std::io::Result<Self> {
+        Ok(Self {
+            connection_id: ConnectionId::from_stream(stream)?,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ClientPhase {
+    Challenge(Nonce, ChallengeRequestId),
     Connecting(Nonce, ServerChallenge),
     Connected(ConnectionId),
+    Disconnecting(ConnectionId),
+    Disconnected,
 }
 
 impl fmt::Display for ClientPhase {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Challenge(nonce) => {
-                write!(f, "clientPhase: Challenge Phase with {}", nonce)
+            Self::Challenge(nonce, request_id) => {
+                write!(
+                    f,
+                    "clientPhase: Challenge Phase with {} and {}",
+                    nonce, request_id
+                )
             }
             Self::Connecting(nonce, challenge) => write!(
                 f,
@@ -269,22 +489,52 @@ impl fmt::Display for ClientPhase {
             Self::Connected(connection_id) => {
                 write!(f, "clientPhase: Connected with {}", *connection_id)
             }
+            Self::Disconnecting(connection_id) => {
+                write!(f, "clientPhase: Disconnecting from {}", *connection_id)
+            }
+            Self::Disconnected => write!(f, "clientPhase: Disconnected"),
         }
     }
 }
 
+/// Why a connection ended, see [`ClientEvent::Disconnected`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DisconnectReason {
+    /// The host acknowledged a disconnect that the client started.
+    Acknowledged,
+    /// The host has been silent for too long, see [`Client::time_out`](prelude::Client::time_out).
+    TimedOut,
+}
+
+/// A connection state change, reported once by [`Client::poll_events`](prelude::Client::poll_events).
+#[derive(Debug, PartialEq)]
+pub enum ClientEvent {
+    /// The host accepted the connect request.
+    Connected { connection_id: ConnectionId },
+    /// The connection has ended.
+    Disconnected {
+        connection_id: ConnectionId,
+        reason: DisconnectReason,
+    },
+}
+
 #[derive(Debug)]
 pub enum DatagramConnectionsError {
     IoError(io::Error),
     ReceiveConnectInWrongPhase,
     WrongNonceWhileConnecting,
     WrongNonceInChallenge,
+    WrongRequestIdInChallenge,
     ReceivedChallengeInWrongPhase,
     WrongConnectionId,
     ReceivedPacketInWrongPhase,
     SendChallengeInWrongPhase,
     SendConnectRequestInWrongPhase,
     SendPacketInWrongPhase,
+    ReceivedDisconnectAckInWrongPhase,
+    SendAfterDisconnected,
+    PacketTooLarge(usize),
+    WrongChallengeProof,
 }
 
 impl Display for DatagramConnectionsError {
@@ -294,3 +544,21 @@ impl Display for DatagramConnectionsError {
 }
 
 impl Error for DatagramConnectionsError {}
+
+/// A malformed datagram from the network, as reported by
+/// [`HostToClientCommands::try_from_stream`](prelude::HostToClientCommands::try_from_stream).
+#[derive(Debug)]
+pub enum ProtocolError {
+    MissingCommand,
+    UnknownCommand(u8),
+    TruncatedCommand { command_id: u8 },
+    IoError(io::Error),
+}
+
+impl Display for ProtocolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for ProtocolError {}
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use crate::host_to_client::HostToClientCommands;
+use crate::stream::remaining;
+use crate::{
+    ChallengeProof, ChallengeRequestId, ClientToHostCommands, ConnectionId, Nonce, ServerChallenge,
+};
+use flood_rs::in_stream::InOctetStream;
+use flood_rs::out_stream::OutOctetStream;
+use std::io;
+
+/// Serializes a value into a plain octet buffer.
+pub trait ToOctets {
+    fn write_octets(&self, stream: &mut OutOctetStream) -> io::Result<()>;
+
+    fn to_octets(&self) -> io::Result<Vec<u8>> {
+        let mut stream = OutOctetStream::new();
+        self.write_octets(&mut stream)?;
+        Ok(stream.octets())
+    }
+}
+
+/// Deserializes a value from a plain octet buffer.
+pub trait FromOctets: Sized {
+    fn read_octets(stream: &mut InOctetStream) -> io::Result<Self>;
+
+    /// Reads a value that must use up all of `octets`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`io::ErrorKind::InvalidData`] error if octets remain after the value.
+    fn from_octets(octets: &[u8]) -> io::Result<Self> {
+        let mut stream = InOctetStream::new(octets);
+        let value = Self::read_octets(&mut stream)?;
+        let trailing = remaining(&stream);
+        if trailing != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{trailing} trailing octets after {}",
+                    std::any::type_name::<Self>()
+                ),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+/// Implements [`ToOctets`] and [`FromOctets`] using the type's `to_stream` and `from_stream`.
+macro_rules! impl_octets {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl ToOctets for $t {
+                fn write_octets(&self, stream: &mut OutOctetStream) -> io::Result<()> {
+                    self.to_stream(stream)
+                }
+            }
+
+            impl FromOctets for $t {
+                fn read_octets(stream: &mut InOctetStream) -> io::Result<Self> {
+                    Self::from_stream(stream)
+                }
+            }
+        )*
+    };
+}
+
+impl_octets!(
+    Nonce,
+    ConnectionId,
+    ServerChallenge,
+    ChallengeProof,
+    ChallengeRequestId,
+    ClientToHostCommands,
+    HostToClientCommands,
+);
@@ -1 +1 @@
-pub use crate::{client::Client, host_to_client::HostToClientCommands};
+pub use crate::{client::Client, host::HostHandshake, host_to_client::HostToClientCommands};
@@ -0,0 +1,45 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use flood_rs::in_stream::InOctetStream;
+use std::io;
+
+/// Returns the number of octets that are left to read in the stream.
+pub fn remaining(stream: &InOctetStream) -> usize {
+    let total = stream.cursor.get_ref().len();
+    total.saturating_sub(stream.cursor.position() as usize)
+}
+
+/// Checks that at least `needed` octets are left before reading `what` from the stream.
+///
+/// # Errors
+///
+/// Returns an [`io::ErrorKind::UnexpectedEof`] error naming `what` and the expected and
+/// available octet counts, instead of the opaque EOF error from a blind read.
+pub fn ensure_remaining(stream: &InOctetStream, needed: usize, what: &str) -> io::Result<()> {
+    let available = remaining(stream);
+    if available < needed {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("{what} is truncated: expected {needed} octets, but only {available} remain"),
+        ));
+    }
+    Ok(())
+}
+
+/// Names `what` in the [`io::ErrorKind::UnexpectedEof`] error of a read from any
+/// [`ReadOctetStream`](flood_rs::ReadOctetStream), for streams that can't tell how many octets are left.
+///
+/// # Errors
+///
+/// Returns the error of `result`, with a descriptive message if it was an unexpected EOF.
+pub fn name_truncated<T>(result: io::Result<T>, what: &str) -> io::Result<T> {
+    result.map_err(|err| {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            io::Error::new(io::ErrorKind::UnexpectedEof, format!("{what} is truncated"))
+        } else {
+            err
+        }
+    })
+}
@@ -4,6 +4,15 @@
  */
 use datagram::DatagramEncoder;
 use datagram_connections::prelude::*;
+use datagram_connections::{
+    ChallengeProof, ChallengeRequestId, ClientEvent, ClientPhase, ClientToHostChallengeCommand,
+    ClientToHostCommands, ClientToHostPacket, ConnectCommand, ConnectResponse, ConnectionId,
+    DatagramConnectionsError, DisconnectAck, DisconnectCommand, DisconnectReason, FromOctets,
+    InChallengeCommand, Nonce, ProtocolError, ServerChallenge, ToOctets,
+};
+use flood_rs::in_stream::InOctetStream;
+use flood_rs::out_stream::OutOctetStream;
+use flood_rs::{Deserialize, Serialize};
 use secure_random::SecureRandom;
 
 #[derive(Debug)]
@@ -33,7 +42,514 @@ fn simple_connection() {
     let expected = vec![
         1, // Challenge command 0x01
         0, 0, 0, 0, 0, 0, 0, 3, // Nonce in network order.
+        0, 0, 0, 0, 0, 0, 0, 4, // Request ID
         0x18, 0x24, 0x32,
     ];
     assert_eq!(datagram_to_send, expected, "upd-connections-was wrong")
 }
+
+#[test_log::test]
+fn stale_challenge_is_rejected() {
+    let random = FakeRandom { counter: 0 };
+    let mut client = Client::new(Box::new(random));
+
+    client.disconnect();
+    client.connect();
+    assert_eq!(
+        *client.phase(),
+        ClientPhase::Challenge(Nonce(3), ChallengeRequestId(4))
+    );
+
+    let stale = InChallengeCommand {
+        nonce: Nonce(1),
+        request_id: ChallengeRequestId(2),
+        incoming_server_challenge: ServerChallenge(0x42),
+    };
+    assert!(matches!(
+        client.on_challenge(stale),
+        Err(DatagramConnectionsError::WrongNonceInChallenge)
+    ));
+
+    let stale_request = InChallengeCommand {
+        nonce: Nonce(3),
+        request_id: ChallengeRequestId(2),
+        incoming_server_challenge: ServerChallenge(0x42),
+    };
+    assert!(matches!(
+        client.on_challenge(stale_request),
+        Err(DatagramConnectionsError::WrongRequestIdInChallenge)
+    ));
+
+    let current = InChallengeCommand {
+        nonce: Nonce(3),
+        request_id: ChallengeRequestId(4),
+        incoming_server_challenge: ServerChallenge(0x42),
+    };
+    assert!(client.on_challenge(current).is_ok());
+}
+
+#[test_log::test]
+fn truncated_challenge_is_descriptive() {
+    let random = FakeRandom { counter: 0 };
+    let mut client = Client::new(Box::new(random));
+
+    let truncated = [0x11, 0x00, 0x00, 0x00];
+    let Err(DatagramConnectionsError::IoError(err)) = client.decode(&truncated) else {
+        panic!("truncated challenge should be an io error");
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(
+        err.to_string(),
+        "InChallengeCommand is truncated: expected 24 octets, but only 3 remain"
+    );
+}
+
+#[test_log::test]
+fn truncated_packet_payload_is_descriptive() {
+    #[rustfmt::skip]
+    let truncated = [
+        0, 0, 0, 0, 0, 0, 0, 1, // Connection ID
+        0x00, 0x04, // Size
+        0x18, 0x28,
+    ];
+    let mut in_stream = InOctetStream::new(&truncated);
+    let err = ClientToHostPacket::from_stream(&mut in_stream).unwrap_err();
+    assert_eq!(err.to_string(), "ClientToHostPacket payload is truncated");
+}
+
+#[test_log::test]
+fn truncated_command_from_stream_names_the_body() {
+    let truncated = [0x12, 0x00, 0x00];
+    let mut in_stream = InOctetStream::new(&truncated);
+    let err = HostToClientCommands::from_stream(&mut in_stream).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(err.to_string(), "ConnectResponse is truncated");
+}
+
+fn connected_client(connection_id: u64) -> Client {
+    let random = FakeRandom { counter: 0 };
+    let mut client = Client::new(Box::new(random));
+
+    #[rustfmt::skip]
+    let challenge = [
+        0x11, // Challenge command
+        0, 0, 0, 0, 0, 0, 0, 1, // Nonce
+        0, 0, 0, 0, 0, 0, 0, 2, // Request ID
+        0, 0, 0, 0, 0, 0, 0, 0x42, // Server challenge
+    ];
+    client
+        .decode(&challenge)
+        .expect("challenge should be accepted");
+
+    let mut connect = vec![
+        0x12, // Connect command
+        0, 0, 0, 0, 0, 0, 0, 1, // Nonce
+    ];
+    connect.extend_from_slice(&connection_id.to_be_bytes());
+    client.decode(&connect).expect("connect should be accepted");
+    assert_eq!(
+        *client.phase(),
+        ClientPhase::Connected(ConnectionId(connection_id))
+    );
+
+    client
+}
+
+#[test_log::test]
+fn connect_keeps_an_established_connection() {
+    let mut client = connected_client(7);
+    client.connect();
+    assert_eq!(*client.phase(), ClientPhase::Connected(ConnectionId(7)));
+}
+
+#[test_log::test]
+fn short_host_packet_is_descriptive_and_keeps_connection() {
+    let mut client = connected_client(7);
+
+    #[rustfmt::skip]
+    let truncated = [
+        0x13, // Packet command
+        0, 0, 0, 0, 0, 0, 0, 7, // Connection ID
+        0x00, 0x0a, // Size
+        0x18, 0x28, 0x38,
+    ];
+    let Err(DatagramConnectionsError::IoError(err)) = client.decode(&truncated) else {
+        panic!("short packet should be an io error");
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(
+        err.to_string(),
+        "HostToClient packet payload is truncated: expected 10 octets, but only 3 remain"
+    );
+    assert_eq!(*client.phase(), ClientPhase::Connected(ConnectionId(7)));
+
+    #[rustfmt::skip]
+    let complete = [
+        0x13, // Packet command
+        0, 0, 0, 0, 0, 0, 0, 7, // Connection ID
+        0x00, 0x02, // Size
+        0x18, 0x28,
+    ];
+    assert_eq!(client.decode(&complete).unwrap(), vec![0x18, 0x28]);
+}
+
+#[test_log::test]
+fn disconnect_commands_round_trip() {
+    let command = ClientToHostCommands::DisconnectType(DisconnectCommand {
+        connection_id: ConnectionId(0x99),
+    });
+    let mut out_stream = OutOctetStream::new();
+    command.to_stream(&mut out_stream).unwrap();
+    let octets = out_stream.octets();
+    assert_eq!(octets, &[0x04, 0, 0, 0, 0, 0, 0, 0, 0x99]);
+
+    let mut in_stream = InOctetStream::new(&octets);
+    let ClientToHostCommands::DisconnectType(decoded) =
+        ClientToHostCommands::from_stream(&mut in_stream).unwrap()
+    else {
+        panic!("expected a disconnect command");
+    };
+    assert_eq!(decoded.connection_id, ConnectionId(0x99));
+
+    let ack = HostToClientCommands::DisconnectAckType(DisconnectAck {
+        connection_id: ConnectionId(0x99),
+    });
+    let mut out_stream = OutOctetStream::new();
+    ack.to_stream(&mut out_stream).unwrap();
+    let octets = out_stream.octets();
+    assert_eq!(octets, &[0x14, 0, 0, 0, 0, 0, 0, 0, 0x99]);
+
+    let mut in_stream = InOctetStream::new(&octets);
+    let HostToClientCommands::DisconnectAckType(decoded) =
+        HostToClientCommands::from_stream(&mut in_stream).unwrap()
+    else {
+        panic!("expected a disconnect ack");
+    };
+    assert_eq!(decoded.connection_id, ConnectionId(0x99));
+}
+
+#[test_log::test]
+fn disconnect_after_ack() {
+    let mut client = connected_client(0x99);
+
+    client.disconnect();
+    assert_eq!(
+        *client.phase(),
+        ClientPhase::Disconnecting(ConnectionId(0x99))
+    );
+
+    let datagram = client.encode(&[]).unwrap();
+    assert_eq!(datagram, &[0x04, 0, 0, 0, 0, 0, 0, 0, 0x99]);
+
+    client
+        .decode(&[0x14, 0, 0, 0, 0, 0, 0, 0, 0x99])
+        .expect("disconnect ack should be accepted");
+    assert_eq!(*client.phase(), ClientPhase::Disconnected);
+    assert!(client.encode(&[]).is_err());
+}
+
+#[test_log::test]
+fn oversized_packet_is_rejected() {
+    let mut client = connected_client(0x99);
+
+    let oversized = vec![0u8; u16::MAX as usize + 1];
+    assert!(matches!(
+        client.send_packet(&oversized),
+        Err(DatagramConnectionsError::PacketTooLarge(65536))
+    ));
+
+    let largest = vec![0u8; u16::MAX as usize];
+    let packet = client
+        .send_packet(&largest)
+        .expect("u16::MAX octets should fit");
+    assert_eq!(packet.header.size, u16::MAX);
+}
+
+#[test_log::test]
+fn host_to_client_commands_round_trip() {
+    #[rustfmt::skip]
+    let commands: [&[u8]; 4] = [
+        &[0x11, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0x42], // Challenge
+        &[0x12, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0x07], // Connect
+        &[0x13, 0, 0, 0, 0, 0, 0, 0, 0x07, 0x00, 0x04],             // Packet header
+        &[0x14, 0, 0, 0, 0, 0, 0, 0, 0x07],                         // DisconnectAck
+    ];
+
+    for octets in commands {
+        let mut in_stream = InOctetStream::new(octets);
+        let command = HostToClientCommands::try_from_stream(&mut in_stream)
+            .unwrap_or_else(|e| panic!("{octets:?} should parse: {e}"));
+
+        let mut out_stream = OutOctetStream::new();
+        command.to_stream(&mut out_stream).unwrap();
+        assert_eq!(out_stream.octets().as_slice(), octets);
+    }
+}
+
+#[test_log::test]
+fn host_to_client_command_without_body_is_truncated() {
+    for command_id in [0x11, 0x12, 0x13, 0x14] {
+        let octets = [command_id];
+        let mut in_stream = InOctetStream::new(&octets);
+        let result = HostToClientCommands::try_from_stream(&mut in_stream);
+        assert!(
+            matches!(result, Err(ProtocolError::TruncatedCommand { command_id: id }) if id == command_id),
+            "unexpected result for {command_id:#x}: {result:?}"
+        );
+    }
+}
+
+#[test_log::test]
+fn host_to_client_unknown_and_missing_command() {
+    let mut in_stream = InOctetStream::new(&[0x7f]);
+    assert!(matches!(
+        HostToClientCommands::try_from_stream(&mut in_stream),
+        Err(ProtocolError::UnknownCommand(0x7f))
+    ));
+
+    let mut in_stream = InOctetStream::new(&[]);
+    assert!(matches!(
+        HostToClientCommands::try_from_stream(&mut in_stream),
+        Err(ProtocolError::MissingCommand)
+    ));
+}
+
+#[test_log::test]
+fn commands_display_as_one_line_summaries() {
+    let challenge = ClientToHostCommands::ChallengeType(ClientToHostChallengeCommand {
+        nonce: Nonce(0x1f),
+        request_id: ChallengeRequestId(0x2e),
+    });
+    assert_eq!(
+        challenge.to_string(),
+        "Challenge(Nonce(1F), ChallengeRequestId(2E))"
+    );
+
+    let packet = ClientToHostCommands::PacketType(
+        ClientToHostPacket::new(ConnectionId(7), &[1, 2, 3]).unwrap(),
+    );
+    assert_eq!(packet.to_string(), "Packet(ConnectionId(7), 3 octets)");
+
+    #[rustfmt::skip]
+    let connect = [
+        0x12, // Connect command
+        0, 0, 0, 0, 0, 0, 0, 1, // Nonce
+        0, 0, 0, 0, 0, 0, 0, 0x2a, // Connection ID
+    ];
+    let mut in_stream = InOctetStream::new(&connect);
+    let command = HostToClientCommands::from_stream(&mut in_stream).unwrap();
+    assert_eq!(command.to_string(), "Connect(Nonce(1), ConnectionId(2A))");
+}
+
+#[test_log::test]
+fn to_and_from_octets() {
+    let nonce = Nonce(0x0102030405060708);
+    let octets = nonce.to_octets().unwrap();
+    assert_eq!(octets, [1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(Nonce::from_octets(&octets).unwrap(), nonce);
+
+    let disconnect = ClientToHostCommands::DisconnectType(DisconnectCommand {
+        connection_id: ConnectionId(7),
+    });
+    let octets = disconnect.to_octets().unwrap();
+    let ClientToHostCommands::DisconnectType(decoded) =
+        ClientToHostCommands::from_octets(&octets).unwrap()
+    else {
+        panic!("should decode as a disconnect command");
+    };
+    assert_eq!(decoded.connection_id, ConnectionId(7));
+}
+
+#[test_log::test]
+fn from_octets_rejects_trailing_octets() {
+    let err = ConnectionId::from_octets(&[0, 0, 0, 0, 0, 0, 0, 1, 0xff]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("1 trailing octets after"));
+}
+
+#[test_log::test]
+fn connected_event_is_reported_once() {
+    let mut client = connected_client(0x42);
+    assert_eq!(
+        client.poll_events(),
+        vec![ClientEvent::Connected {
+            connection_id: ConnectionId(0x42)
+        }]
+    );
+    assert!(client.poll_events().is_empty());
+
+    client.disconnect();
+    client
+        .decode(&[0x14, 0, 0, 0, 0, 0, 0, 0, 0x42])
+        .expect("disconnect ack should be accepted");
+    assert_eq!(
+        client.poll_events(),
+        vec![ClientEvent::Disconnected {
+            connection_id: ConnectionId(0x42),
+            reason: DisconnectReason::Acknowledged,
+        }]
+    );
+}
+
+#[test_log::test]
+fn time_out_ends_connection() {
+    let mut client = connected_client(0x42);
+    client.poll_events();
+
+    client.time_out();
+    assert_eq!(*client.phase(), ClientPhase::Disconnected);
+    assert_eq!(
+        client.poll_events(),
+        vec![ClientEvent::Disconnected {
+            connection_id: ConnectionId(0x42),
+            reason: DisconnectReason::TimedOut,
+        }]
+    );
+
+    client.time_out();
+    assert!(client.poll_events().is_empty());
+}
+
+#[test_log::test]
+fn time_out_keeps_retrying_handshake() {
+    let mut client = Client::new(Box::new(FakeRandom { counter: 0 }));
+    let phase = *client.phase();
+
+    client.time_out();
+    assert_eq!(*client.phase(), phase);
+    assert!(client.poll_events().is_empty());
+}
+
+fn round_trip<T: Serialize + Deserialize>(value: &T) -> T {
+    let mut out_stream = OutOctetStream::new();
+    value.serialize(&mut out_stream).unwrap();
+    let octets = out_stream.octets();
+    let mut in_stream = InOctetStream::new(&octets);
+    T::deserialize(&mut in_stream).unwrap()
+}
+
+#[test_log::test]
+fn handshake_types_use_flood_traits() {
+    let challenge = InChallengeCommand {
+        nonce: Nonce(1),
+        request_id: ChallengeRequestId(2),
+        incoming_server_challenge: ServerChallenge(0x42),
+    };
+    assert_eq!(round_trip(&challenge), challenge);
+
+    let connect = ConnectCommand {
+        nonce: Nonce(1),
+        challenge_proof: ChallengeProof(0x42),
+    };
+    assert_eq!(round_trip(&connect), connect);
+
+    let response = ConnectResponse {
+        nonce: Nonce(1),
+        connection_id: ConnectionId(7),
+    };
+    assert_eq!(round_trip(&response), response);
+}
+
+/// Pins the big-endian (network order) layout of every integer width on the wire, so that
+/// a change in flood-rs can't silently break compatibility with the C host.
+#[test_log::test]
+fn integers_are_big_endian() {
+    const U64_OCTETS: [u8; 8] = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+
+    assert_eq!(Nonce(0x0102030405060708).to_octets().unwrap(), U64_OCTETS);
+    assert_eq!(
+        ConnectionId(0x0102030405060708).to_octets().unwrap(),
+        U64_OCTETS
+    );
+    assert_eq!(
+        ServerChallenge(0x0102030405060708).to_octets().unwrap(),
+        U64_OCTETS
+    );
+    assert_eq!(
+        ChallengeRequestId(0x0102030405060708).to_octets().unwrap(),
+        U64_OCTETS
+    );
+
+    let packet =
+        ClientToHostPacket::new(ConnectionId(0x0102030405060708), &[0xaa; 0x0102]).unwrap();
+    let mut out_stream = OutOctetStream::new();
+    packet.header.to_stream(&mut out_stream).unwrap();
+    #[rustfmt::skip]
+    let expected_header = [
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // Connection ID
+        0x01, 0x02, // Size
+    ];
+    assert_eq!(out_stream.octets_ref(), &expected_header);
+}
+
+/// Runs the client through the challenge against `host` and returns the connect request.
+fn connect_request(client: &mut Client, host: &HostHandshake) -> ConnectCommand {
+    let ClientToHostCommands::ChallengeType(challenge_request) = client.send(&[]).unwrap() else {
+        panic!("expected a challenge request");
+    };
+    let challenge = HostToClientCommands::ChallengeType(host.on_challenge(&challenge_request));
+    client.decode(&challenge.to_octets().unwrap()).unwrap();
+
+    let connect_datagram = client.encode(&[]).unwrap();
+    let mut in_stream = InOctetStream::new(&connect_datagram);
+    let ClientToHostCommands::ConnectType(connect) =
+        ClientToHostCommands::from_stream(&mut in_stream).unwrap()
+    else {
+        panic!("expected a connect request");
+    };
+    connect
+}
+
+#[test_log::test]
+fn host_accepts_correct_challenge_proof() {
+    let mut host = HostHandshake::new(Box::new(FakeRandom { counter: 100 }));
+    let mut client = Client::new(Box::new(FakeRandom { counter: 0 }));
+
+    let connect = connect_request(&mut client, &host);
+    let ClientPhase::Connecting(nonce, challenge) = *client.phase() else {
+        panic!("expected the client to be connecting");
+    };
+    assert_eq!(connect.nonce, nonce);
+    assert_eq!(connect.challenge_proof, challenge.proof(nonce));
+    assert_ne!(connect.challenge_proof.0, challenge.0);
+
+    let response = host.on_connect(&connect).unwrap();
+    let response = HostToClientCommands::ConnectType(response);
+    client.decode(&response.to_octets().unwrap()).unwrap();
+    assert_eq!(*client.phase(), ClientPhase::Connected(ConnectionId(102)));
+}
+
+#[test_log::test]
+fn host_rejects_wrong_challenge_proof() {
+    let mut host = HostHandshake::new(Box::new(FakeRandom { counter: 100 }));
+    let mut client = Client::new(Box::new(FakeRandom { counter: 0 }));
+    let connect = connect_request(&mut client, &host);
+
+    let echoed_challenge = ConnectCommand {
+        nonce: connect.nonce,
+        challenge_proof: ChallengeProof(0x42),
+    };
+    assert!(matches!(
+        host.on_connect(&echoed_challenge),
+        Err(DatagramConnectionsError::WrongChallengeProof)
+    ));
+
+    let other_nonce = ConnectCommand {
+        nonce: Nonce(connect.nonce.0 + 1),
+        challenge_proof: connect.challenge_proof,
+    };
+    assert!(matches!(
+        host.on_connect(&other_nonce),
+        Err(DatagramConnectionsError::WrongChallengeProof)
+    ));
+}
+
+#[test_log::test]
+fn challenge_proof_depends_on_nonce_and_challenge() {
+    let proof = ServerChallenge(0x42).proof(Nonce(1));
+
+    assert_eq!(proof, ServerChallenge(0x42).proof(Nonce(1)));
+    assert_ne!(proof, ServerChallenge(0x42).proof(Nonce(2)));
+    assert_ne!(proof, ServerChallenge(0x43).proof(Nonce(1)));
+    assert_ne!(proof, ServerChallenge(0x42 << 32).proof(Nonce(1)));
+}
@@ -0,0 +1,90 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use datagram::{DatagramCommunicator, DatagramReceiver, DatagramSender};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::io;
+
+/// Wraps a communicator and simulates a lossy, high latency link.
+///
+/// Every sent and received datagram is dropped with `drop_probability`, using an RNG
+/// seeded at construction so that a test sees the same losses on every run.
+/// Received datagrams that survive are held back for `receive_delay` calls to
+/// `receive` before they are handed out, in the order they arrived.
+pub struct ImpairedCommunicator<C: DatagramCommunicator> {
+    pub inner: C,
+    /// Chance, from `0.0` to `1.0`, that a datagram is dropped.
+    pub drop_probability: f64,
+    /// Number of `receive` calls a datagram is held back for.
+    pub receive_delay: usize,
+    rng: StdRng,
+    receive_count: usize,
+    delayed: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl<C: DatagramCommunicator> ImpairedCommunicator<C> {
+    /// Creates a communicator that forwards everything unchanged until
+    /// `drop_probability` or `receive_delay` are set.
+    pub fn new(inner: C, seed: u64) -> Self {
+        Self {
+            inner,
+            drop_probability: 0.0,
+            receive_delay: 0,
+            rng: StdRng::seed_from_u64(seed),
+            receive_count: 0,
+            delayed: VecDeque::new(),
+        }
+    }
+
+    fn should_drop(&mut self) -> bool {
+        self.drop_probability > 0.0 && self.rng.gen_bool(self.drop_probability.min(1.0))
+    }
+
+    /// Moves everything the inner communicator has received into the delay queue.
+    fn pull_incoming(&mut self, buffer: &mut [u8]) -> io::Result<()> {
+        loop {
+            let size = match self.inner.receive(buffer) {
+                Ok(0) => return Ok(()),
+                Ok(size) => size,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            if self.should_drop() {
+                continue;
+            }
+            let release_at = self.receive_count + self.receive_delay;
+            self.delayed
+                .push_back((release_at, buffer[..size].to_vec()));
+        }
+    }
+}
+
+impl<C: DatagramCommunicator> DatagramSender for ImpairedCommunicator<C> {
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.should_drop() {
+            return Ok(());
+        }
+        self.inner.send(data)
+    }
+}
+
+impl<C: DatagramCommunicator> DatagramReceiver for ImpairedCommunicator<C> {
+    /// Returns `Ok(0)` while no datagram has been delayed long enough.
+    fn receive(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.receive_count += 1;
+        self.pull_incoming(buffer)?;
+
+        match self.delayed.front() {
+            Some((release_at, _)) if *release_at <= self.receive_count => {
+                let (_, datagram) = self.delayed.pop_front().unwrap();
+                let size = datagram.len().min(buffer.len());
+                buffer[..size].copy_from_slice(&datagram[..size]);
+                Ok(size)
+            }
+            _ => Ok(0),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+mod impaired;
+mod loopback;
+mod recording;
+
+pub use impaired::ImpairedCommunicator;
+pub use loopback::LoopbackCommunicator;
+pub use recording::RecordingCodec;
@@ -0,0 +1,73 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use datagram::{DatagramReceiver, DatagramSender};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+
+type DatagramQueue = Rc<RefCell<VecDeque<Vec<u8>>>>;
+
+/// One end of an in-memory datagram link, created with [`LoopbackCommunicator::pair`].
+///
+/// Datagrams sent on one end are received, in order, on the other end.
+/// `receive` returns `Ok(0)` when nothing is queued, matching a nonblocking socket
+/// with no pending data.
+#[derive(Debug)]
+pub struct LoopbackCommunicator {
+    incoming: DatagramQueue,
+    outgoing: DatagramQueue,
+}
+
+impl LoopbackCommunicator {
+    /// Creates two ends that are wired to each other.
+    pub fn pair() -> (Self, Self) {
+        let a_to_b = DatagramQueue::default();
+        let b_to_a = DatagramQueue::default();
+
+        (
+            Self {
+                incoming: b_to_a.clone(),
+                outgoing: a_to_b.clone(),
+            },
+            Self {
+                incoming: a_to_b,
+                outgoing: b_to_a,
+            },
+        )
+    }
+
+    /// Number of datagrams waiting to be received on this end.
+    pub fn pending_incoming(&self) -> usize {
+        self.incoming.borrow().len()
+    }
+
+    /// Number of datagrams sent from this end that the other end has not received yet.
+    pub fn pending_outgoing(&self) -> usize {
+        self.outgoing.borrow().len()
+    }
+}
+
+impl DatagramSender for LoopbackCommunicator {
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.outgoing.borrow_mut().push_back(data.to_vec());
+        Ok(())
+    }
+}
+
+impl DatagramReceiver for LoopbackCommunicator {
+    /// Copies the oldest queued datagram into `buffer`. Like UDP, a datagram that
+    /// does not fit is truncated.
+    fn receive(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self.incoming.borrow_mut().pop_front() {
+            Some(datagram) => {
+                let size = datagram.len().min(buffer.len());
+                buffer[..size].copy_from_slice(&datagram[..size]);
+                Ok(size)
+            }
+            None => Ok(0),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use datagram::{DatagramCodec, DatagramDecoder, DatagramEncoder};
+use std::io;
+
+/// Wraps a codec and records the datagrams as they appear on the wire.
+///
+/// `sent` holds the output of every successful `encode`, and `received` holds the input
+/// of every `decode`, so together they capture the exact octets exchanged in a session.
+pub struct RecordingCodec<C: DatagramCodec> {
+    pub inner: C,
+    pub sent: Vec<Vec<u8>>,
+    pub received: Vec<Vec<u8>>,
+}
+
+impl<C: DatagramCodec> RecordingCodec<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            sent: Vec::new(),
+            received: Vec::new(),
+        }
+    }
+}
+
+impl<C: DatagramCodec> DatagramEncoder for RecordingCodec<C> {
+    fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        let encoded = self.inner.encode(buf)?;
+        self.sent.push(encoded.clone());
+        Ok(encoded)
+    }
+}
+
+impl<C: DatagramCodec> DatagramDecoder for RecordingCodec<C> {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        self.received.push(buf.to_vec());
+        self.inner.decode(buf)
+    }
+}
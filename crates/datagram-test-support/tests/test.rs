@@ -0,0 +1,90 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use datagram::{DatagramDecoder, DatagramEncoder, DatagramReceiver, DatagramSender};
+use datagram_test_support::{ImpairedCommunicator, LoopbackCommunicator, RecordingCodec};
+use std::io;
+
+#[test_log::test]
+fn loopback_pair() {
+    let (mut client, mut host) = LoopbackCommunicator::pair();
+
+    client.send(&[0x18, 0x28]).unwrap();
+    assert_eq!(client.pending_outgoing(), 1);
+    assert_eq!(host.pending_incoming(), 1);
+
+    let mut buf = [0u8; 1200];
+    let size = host.receive(&mut buf).unwrap();
+    assert_eq!(&buf[..size], &[0x18, 0x28]);
+
+    assert_eq!(host.receive(&mut buf).unwrap(), 0);
+    assert_eq!(client.receive(&mut buf).unwrap(), 0);
+}
+
+struct IdentityCodec;
+
+impl DatagramEncoder for IdentityCodec {
+    fn encode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(buf.to_vec())
+    }
+}
+
+impl DatagramDecoder for IdentityCodec {
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(buf.to_vec())
+    }
+}
+
+#[test_log::test]
+fn recording_codec() -> io::Result<()> {
+    let mut codec = RecordingCodec::new(IdentityCodec);
+
+    assert_eq!(codec.encode(&[0x01, 0x02])?, &[0x01, 0x02]);
+    assert_eq!(codec.encode(&[0x03])?, &[0x03]);
+    assert_eq!(codec.decode(&[0xfe])?, &[0xfe]);
+
+    assert_eq!(codec.sent, vec![vec![0x01, 0x02], vec![0x03]]);
+    assert_eq!(codec.received, vec![vec![0xfe]]);
+
+    Ok(())
+}
+
+#[test_log::test]
+fn impaired_drops_everything() {
+    let (client, mut host) = LoopbackCommunicator::pair();
+    let mut impaired = ImpairedCommunicator::new(client, 42);
+    impaired.drop_probability = 1.0;
+
+    impaired.send(&[0x01]).unwrap();
+    assert_eq!(host.pending_incoming(), 0);
+
+    for octet in 0..10 {
+        host.send(&[octet]).unwrap();
+    }
+    let mut buf = [0u8; 1200];
+    for _ in 0..10 {
+        assert_eq!(impaired.receive(&mut buf).unwrap(), 0);
+    }
+    assert_eq!(host.pending_outgoing(), 0);
+}
+
+#[test_log::test]
+fn impaired_delays_in_order() {
+    let (client, mut host) = LoopbackCommunicator::pair();
+    let mut impaired = ImpairedCommunicator::new(client, 42);
+    impaired.receive_delay = 2;
+
+    host.send(&[0x01]).unwrap();
+    host.send(&[0x02]).unwrap();
+
+    let mut buf = [0u8; 1200];
+    assert_eq!(impaired.receive(&mut buf).unwrap(), 0);
+    assert_eq!(impaired.receive(&mut buf).unwrap(), 0);
+
+    let size = impaired.receive(&mut buf).unwrap();
+    assert_eq!(&buf[..size], &[0x01]);
+    let size = impaired.receive(&mut buf).unwrap();
+    assert_eq!(&buf[..size], &[0x02]);
+    assert_eq!(impaired.receive(&mut buf).unwrap(), 0);
+}
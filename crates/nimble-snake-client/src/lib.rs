@@ -2,61 +2,123 @@ use flood_rs::BufferDeserializer;
 use hexify::format_hex;
 use log::debug;
 use nimble_client_with_codec::{
-    AssentCallback, ClientWithCodec, RectifyCallback, SeerCallback, Step, StepMap, Version,
-    VersionProvider, WrappedOctetStep,
+    AssentCallback, ClientError, ClientWithCodec, RectifyCallback, SeerCallback, Step, StepMap,
+    Version, VersionProvider, WrappedOctetStep,
 };
 use crate::snake_c::{ExampleGame, ExamplePlayerInput};
+use std::fmt::Debug;
 
+pub use simulation::ExampleSimulation;
+
+mod simulation;
 pub mod snake_c;
 
+/// Advances an [`ExampleGame`] by one tick, after the participant inputs for the tick
+/// have been set on it.
+///
+/// [`ExampleSimulation`] is used by default. A client that links the C example game can
+/// tick that instead. [`SnakeGame`] creates the simulation with [`Default`], since the
+/// game can be received from the host.
+pub trait SnakeSimulation: Debug + Default {
+    fn tick(&mut self, game: &mut ExampleGame);
+}
+
 #[derive(Debug)]
-pub struct SnakeGame {
+pub struct SnakeGame<SimT: SnakeSimulation = ExampleSimulation> {
     authoritative: ExampleGame,
     predicted: ExampleGame,
+    simulation: SimT,
 }
 
-impl VersionProvider for SnakeGame {
+impl<SimT: SnakeSimulation> SnakeGame<SimT> {
+    pub fn new(game: ExampleGame) -> Self {
+        Self {
+            authoritative: game,
+            predicted: game,
+            simulation: SimT::default(),
+        }
+    }
+
+    pub fn authoritative(&self) -> &ExampleGame {
+        &self.authoritative
+    }
+
+    pub fn predicted(&self) -> &ExampleGame {
+        &self.predicted
+    }
+}
+
+impl<SimT: SnakeSimulation> VersionProvider for SnakeGame<SimT> {
     fn version() -> Version {
         Version::new(1, 0, 0)
     }
 }
 
-impl BufferDeserializer for SnakeGame {
+impl<SimT: SnakeSimulation> BufferDeserializer for SnakeGame<SimT> {
     fn deserialize(buf: &[u8]) -> std::io::Result<(Self, usize)> {
         debug!("{}", format_hex(&buf));
-        let game: ExampleGame = buf.try_into().unwrap();
+        let size = size_of::<ExampleGame>();
+        if buf.len() < size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!(
+                    "SnakeGame state is truncated: expected {size} octets, but received {}",
+                    buf.len()
+                ),
+            ));
+        }
+        let game: ExampleGame = buf[..size]
+            .try_into()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
         debug!(
             "received game. arena:{:?} food:{:?}.\n{game:?}",
             game.area, game.food.position
         );
-        Ok((
-            SnakeGame {
-                authoritative: game,
-                predicted: game,
-            },
-            size_of::<ExampleGame>(),
-        ))
+        Ok((Self::new(game), size))
     }
 }
 
 pub type SnakeStep = WrappedOctetStep<ExamplePlayerInput>;
 pub type AuthSnakeStep = StepMap<Step<SnakeStep>>;
 
-impl RectifyCallback for SnakeGame {
+impl<SimT: SnakeSimulation> RectifyCallback for SnakeGame<SimT> {
     fn on_copy_from_authoritative(&mut self) {
         self.predicted = self.authoritative.clone();
     }
 }
 
-impl AssentCallback<AuthSnakeStep> for SnakeGame {
+/// Applies the participant steps for one tick to the game and then ticks the simulation.
+fn apply_step(
+    game: &mut ExampleGame,
+    step: &AuthSnakeStep,
+    simulation: &mut impl SnakeSimulation,
+) {
+    for (participant_id, participant_step) in step.iter() {
+        match participant_step {
+            Step::Custom(wrapped_input) => {
+                game.set_participant_input(participant_id.0, wrapped_input.step)
+            }
+            Step::Joined(_) => game.participant_joined(participant_id.0),
+            Step::Left => game.participant_left(participant_id.0),
+            // The host had no input from the participant in time, so the snake keeps
+            // going in its current direction until the participant is heard from again.
+            Step::Forced | Step::WaitingForReconnect => {
+                game.set_participant_input(participant_id.0, ExamplePlayerInput::default())
+            }
+        }
+    }
+    simulation.tick(game);
+}
+
+impl<SimT: SnakeSimulation> AssentCallback<AuthSnakeStep> for SnakeGame<SimT> {
     fn on_tick(&mut self, step: &AuthSnakeStep) {
-        // TODO:
+        apply_step(&mut self.authoritative, step, &mut self.simulation);
     }
 }
 
-impl SeerCallback<AuthSnakeStep> for SnakeGame {
+impl<SimT: SnakeSimulation> SeerCallback<AuthSnakeStep> for SnakeGame<SimT> {
     fn on_tick(&mut self, step: &AuthSnakeStep) {
-        // TODO:
+        apply_step(&mut self.predicted, step, &mut self.simulation);
     }
 }
 
@@ -65,10 +127,10 @@ pub struct SnakeClient {
 }
 
 impl SnakeClient {
-    pub fn new(url: &str) -> Self {
-        Self {
-            client: ClientWithCodec::new(url),
-        }
+    pub fn new(url: &str) -> Result<Self, ClientError> {
+        Ok(Self {
+            client: ClientWithCodec::new(url)?,
+        })
     }
 
     pub fn client(&self) -> &ClientWithCodec<SnakeGame, SnakeStep> {
@@ -10,7 +10,7 @@ use tick_id::TickId;
 fn main() -> Result<(), ClientError> {
     env_logger::init();
 
-    let mut snake_client = SnakeClient::new("127.0.0.1:23000");
+    let mut snake_client = SnakeClient::new("127.0.0.1:23000")?;
     let clock = monotonic_time_rs::InstantMonotonicClock::new();
     let client_with_codec = &mut snake_client.client_mut();
 
@@ -0,0 +1,136 @@
+use crate::snake_c::{
+    ExampleDirection, ExampleGame, ExamplePlayerInputType, ExamplePosition, ExampleSnake,
+    EXAMPLE_GAME_MAX_AVATARS, NIMBLE_EXAMPLE_SNAKE_MAX_LENGTH,
+};
+use crate::SnakeSimulation;
+use std::os::raw::c_int;
+
+/// Moves every snake one cell per tick, steered by the in-game input of its player.
+///
+/// A positive horizontal axis turns right and a negative one left. A positive vertical
+/// axis turns up (towards smaller `y`) and a negative one down. A snake can't turn back
+/// into its own body. Snakes wrap around the edges of the area, and a snake that reaches
+/// the food grows by one and the food moves to a new pseudo-random position.
+#[derive(Debug, Default)]
+pub struct ExampleSimulation;
+
+impl SnakeSimulation for ExampleSimulation {
+    fn tick(&mut self, game: &mut ExampleGame) {
+        let snake_count = (game.snakes.snakeCount as usize).min(EXAMPLE_GAME_MAX_AVATARS);
+        for snake_index in 0..snake_count {
+            let snake = game.snakes.snakes[snake_index];
+            if snake.isFrozen.to_bool() {
+                continue;
+            }
+            let direction = steered_direction(game, &snake);
+            let moved = move_snake(snake, direction, game);
+            game.snakes.snakes[snake_index] = moved;
+
+            if same_position(moved.body[0], game.food.position) {
+                grow(&mut game.snakes.snakes[snake_index]);
+                game.food.position = next_food_position(game);
+            }
+        }
+    }
+}
+
+fn steered_direction(game: &ExampleGame, snake: &ExampleSnake) -> ExampleDirection {
+    let Some(player) = game
+        .players
+        .players
+        .get(snake.controlledByPlayerIndex as usize)
+    else {
+        return snake.movementDirection;
+    };
+    if player.playerInput.inputType != ExamplePlayerInputType::InGame {
+        return snake.movementDirection;
+    }
+    // SAFETY: `inputType` is `InGame`, so `inGameInput` is the active union field.
+    let input = unsafe { player.playerInput.input.inGameInput };
+    let wanted = match (input.horizontalAxis.signum(), input.verticalAxis.signum()) {
+        (1, _) => ExampleDirection::Right,
+        (-1, _) => ExampleDirection::Left,
+        (_, 1) => ExampleDirection::Up,
+        (_, -1) => ExampleDirection::Down,
+        _ => return snake.movementDirection,
+    };
+    if snake.length > 1 && is_opposite(wanted, snake.movementDirection) {
+        return snake.movementDirection;
+    }
+    wanted
+}
+
+const fn is_opposite(a: ExampleDirection, b: ExampleDirection) -> bool {
+    matches!(
+        (a, b),
+        (ExampleDirection::Up, ExampleDirection::Down)
+            | (ExampleDirection::Down, ExampleDirection::Up)
+            | (ExampleDirection::Left, ExampleDirection::Right)
+            | (ExampleDirection::Right, ExampleDirection::Left)
+    )
+}
+
+fn move_snake(
+    mut snake: ExampleSnake,
+    direction: ExampleDirection,
+    game: &ExampleGame,
+) -> ExampleSnake {
+    let length = snake_length(&snake);
+    snake.body.copy_within(0..length - 1, 1);
+
+    let head = snake.body[0];
+    let (dx, dy) = match direction {
+        ExampleDirection::Up => (0, -1),
+        ExampleDirection::Right => (1, 0),
+        ExampleDirection::Down => (0, 1),
+        ExampleDirection::Left => (-1, 0),
+    };
+    snake.body[0] = ExamplePosition {
+        x: wrap(head.x.wrapping_add(dx), game.area.width),
+        y: wrap(head.y.wrapping_add(dy), game.area.height),
+    };
+    snake.movementDirection = direction;
+    snake.length = length as c_int;
+    snake
+}
+
+/// Clamps the host-supplied length to the body array, with at least the head.
+fn snake_length(snake: &ExampleSnake) -> usize {
+    (snake.length.max(1) as usize).min(NIMBLE_EXAMPLE_SNAKE_MAX_LENGTH)
+}
+
+fn wrap(value: c_int, size: usize) -> c_int {
+    match c_int::try_from(size) {
+        Ok(size) if size > 0 => value.rem_euclid(size),
+        _ => value,
+    }
+}
+
+const fn same_position(a: ExamplePosition, b: ExamplePosition) -> bool {
+    a.x == b.x && a.y == b.y
+}
+
+fn grow(snake: &mut ExampleSnake) {
+    let length = snake_length(snake);
+    if length < NIMBLE_EXAMPLE_SNAKE_MAX_LENGTH {
+        // The new tail segment starts on top of the old one and separates on the next move.
+        snake.body[length] = snake.body[length - 1];
+        snake.length = (length + 1) as c_int;
+    }
+}
+
+fn next_food_position(game: &mut ExampleGame) -> ExamplePosition {
+    let mut next = || {
+        game.pseudoRandom = game
+            .pseudoRandom
+            .wrapping_mul(1_103_515_245)
+            .wrapping_add(12_345);
+        game.pseudoRandom >> 16
+    };
+    let x = next();
+    let y = next();
+    ExamplePosition {
+        x: wrap(x as c_int, game.area.width),
+        y: wrap(y as c_int, game.area.height),
+    }
+}
@@ -1,5 +1,6 @@
 use flood_rs::{Deserialize, ReadOctetStream, Serialize, WriteOctetStream};
 use std::fmt::{Debug, Display, Formatter};
+use std::mem::offset_of;
 use std::{mem, slice};
 use std::os::raw::c_int;
 
@@ -232,12 +233,175 @@ impl TryFrom<&[u8]> for ExampleGame {
         let size = size_of::<ExampleGame>();
 
         // Ensure the slice length matches the size of the struct
-        if slice.len() == size {
-            // SAFETY: We assume the slice is valid and correctly aligned for ExampleGame
-            let ptr = slice.as_ptr() as *const ExampleGame;
-            unsafe { Ok(*ptr) } // Dereference the pointer to get the struct
-        } else {
-            Err("Invalid slice length")
+        if slice.len() != size {
+            return Err("Invalid slice length");
+        }
+        validate_enum_values(slice)?;
+        // SAFETY: The length is checked above, and every enum field holds one of its
+        // discriminants, so the octets are a valid ExampleGame. All other fields are plain
+        // integers, CBool octets or unions of those. The slice is not guaranteed to be
+        // aligned for ExampleGame, so it must be read unaligned.
+        let ptr = slice.as_ptr() as *const ExampleGame;
+        unsafe { Ok(ptr.read_unaligned()) }
+    }
+}
+
+// The enum fields are read as C ints in `validate_enum_values`.
+const _: () = assert!(size_of::<ExampleDirection>() == size_of::<u32>());
+const _: () = assert!(size_of::<ExamplePlayerInputType>() == size_of::<u32>());
+
+fn enum_value_at(slice: &[u8], offset: usize) -> u32 {
+    let mut octets = [0u8; size_of::<u32>()];
+    octets.copy_from_slice(&slice[offset..offset + size_of::<u32>()]);
+    u32::from_ne_bytes(octets)
+}
+
+/// Checks that the enum fields in the raw `ExampleGame` octets hold known discriminants.
+/// Reading any other value into a Rust enum is undefined behavior.
+fn validate_enum_values(slice: &[u8]) -> Result<(), &'static str> {
+    let snakes_offset = offset_of!(ExampleGame, snakes) + offset_of!(ExampleSnakes, snakes);
+    for snake_index in 0..EXAMPLE_GAME_MAX_AVATARS {
+        let offset = snakes_offset
+            + snake_index * size_of::<ExampleSnake>()
+            + offset_of!(ExampleSnake, movementDirection);
+        if enum_value_at(slice, offset) > ExampleDirection::Left as u32 {
+            return Err("Invalid ExampleDirection");
+        }
+    }
+
+    let players_offset = offset_of!(ExampleGame, players) + offset_of!(ExamplePlayers, players);
+    for player_index in 0..EXAMPLE_GAME_MAX_PLAYERS {
+        let offset = players_offset
+            + player_index * size_of::<ExamplePlayer>()
+            + offset_of!(ExamplePlayer, playerInput)
+            + offset_of!(ExamplePlayerInput, inputType);
+        if enum_value_at(slice, offset) > ExamplePlayerInputType::SelectTeam as u32 {
+            return Err("Invalid ExamplePlayerInputType");
+        }
+    }
+    Ok(())
+}
+
+impl ExampleGame {
+    fn participant_lookup_index(&self, participant_id: u8) -> Option<usize> {
+        self.participantLookup.iter().position(|participant| {
+            participant.isUsed.to_bool() && participant.participantId == participant_id
+        })
+    }
+
+    fn update_participant_lookup_count(&mut self) {
+        self.lastParticipantLookupCount = self
+            .participantLookup
+            .iter()
+            .filter(|participant| participant.isUsed.to_bool())
+            .count() as u8;
+    }
+
+    /// Gives the player a snake, reusing a snake that no player controls. A new snake starts
+    /// in the middle of the area with length one.
+    ///
+    /// The game state comes from the host, so counts and indices are clamped to the array
+    /// sizes instead of being trusted.
+    fn assign_snake(&mut self, player_index: usize) {
+        let snake_count = (self.snakes.snakeCount as usize).min(EXAMPLE_GAME_MAX_AVATARS);
+        let snake_index = match self.snakes.snakes[..snake_count]
+            .iter()
+            .position(|snake| snake.controlledByPlayerIndex == EXAMPLE_ILLEGAL_INDEX)
+        {
+            Some(free_snake_index) => free_snake_index,
+            None if snake_count < EXAMPLE_GAME_MAX_AVATARS => {
+                let mut snake = ExampleSnake::default();
+                snake.body[0] = ExamplePosition {
+                    x: (self.area.width / 2) as c_int,
+                    y: (self.area.height / 2) as c_int,
+                };
+                snake.length = 1;
+                self.snakes.snakes[snake_count] = snake;
+                self.snakes.snakeCount = (snake_count + 1) as u8;
+                snake_count
+            }
+            None => return,
+        };
+
+        let Some(player) = self.players.players.get_mut(player_index) else {
+            return;
+        };
+        player.snakeIndex = snake_index as u8;
+        self.snakes.snakes[snake_index].controlledByPlayerIndex = player_index as u8;
+    }
+
+    /// Registers a participant and assigns it a player and a snake, reusing those that
+    /// have been left.
+    pub fn participant_joined(&mut self, participant_id: u8) {
+        if self.participant_lookup_index(participant_id).is_some() {
+            return;
+        }
+
+        let Some(lookup_index) = self
+            .participantLookup
+            .iter()
+            .position(|participant| !participant.isUsed.to_bool())
+        else {
+            return;
+        };
+
+        let player_count = (self.players.playerCount as usize).min(EXAMPLE_GAME_MAX_PLAYERS);
+        let player_index = match self.players.players[..player_count]
+            .iter()
+            .position(|player| player.assignedToParticipantIndex == EXAMPLE_ILLEGAL_INDEX)
+        {
+            Some(free_player_index) => free_player_index,
+            None if player_count < EXAMPLE_GAME_MAX_PLAYERS => {
+                self.players.playerCount = (player_count + 1) as u8;
+                player_count
+            }
+            None => return,
+        };
+
+        self.players.players[player_index] = ExamplePlayer {
+            snakeIndex: EXAMPLE_ILLEGAL_INDEX,
+            assignedToParticipantIndex: lookup_index as u8,
+            playerIndex: player_index as u8,
+            playerInput: ExamplePlayerInput::default(),
+        };
+        self.participantLookup[lookup_index] = ExampleParticipant {
+            participantId: participant_id,
+            playerIndex: player_index as u8,
+            isUsed: CBool::from_bool(true),
+            internalMarked: CBool::default(),
+        };
+        self.assign_snake(player_index);
+        self.update_participant_lookup_count();
+    }
+
+    /// Unregisters a participant, releasing its player and snake.
+    pub fn participant_left(&mut self, participant_id: u8) {
+        let Some(lookup_index) = self.participant_lookup_index(participant_id) else {
+            return;
+        };
+
+        let player_index = self.participantLookup[lookup_index].playerIndex as usize;
+        if let Some(player) = self.players.players.get_mut(player_index) {
+            player.assignedToParticipantIndex = EXAMPLE_ILLEGAL_INDEX;
+            player.playerInput = ExamplePlayerInput::default();
+            let snake_index = player.snakeIndex as usize;
+            player.snakeIndex = EXAMPLE_ILLEGAL_INDEX;
+            if let Some(snake) = self.snakes.snakes.get_mut(snake_index) {
+                snake.controlledByPlayerIndex = EXAMPLE_ILLEGAL_INDEX;
+            }
+        }
+
+        self.participantLookup[lookup_index] = ExampleParticipant::default();
+        self.update_participant_lookup_count();
+    }
+
+    /// Sets the input for the player controlled by the participant. Unknown participants are ignored.
+    pub fn set_participant_input(&mut self, participant_id: u8, input: ExamplePlayerInput) {
+        if let Some(lookup_index) = self.participant_lookup_index(participant_id) {
+            let player_index = self.participantLookup[lookup_index].playerIndex as usize;
+            if let Some(player) = self.players.players.get_mut(player_index) {
+                player.playerInput = input;
+            }
         }
     }
 }
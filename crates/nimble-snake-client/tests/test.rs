@@ -0,0 +1,246 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use flood_rs::BufferDeserializer;
+use nimble_client_with_codec::{AssentCallback, SeerCallback, Step};
+use nimble_participant::ParticipantId;
+use nimble_snake_client::snake_c::{
+    ExampleDirection, ExampleGame, ExampleGameArea, ExamplePlayer, ExamplePlayerInGameInput,
+    ExamplePlayerInput, ExamplePlayerInputType, ExamplePlayerInputUnion, ExamplePlayers,
+    ExamplePosition, ExampleSnake, ExampleSnakes, EXAMPLE_ILLEGAL_INDEX,
+};
+use nimble_snake_client::{
+    AuthSnakeStep, ExampleSimulation, SnakeGame, SnakeSimulation, SnakeStep,
+};
+use std::mem::offset_of;
+use std::os::raw::c_int;
+
+/// Stands in for the C tick: moves each snake head by the horizontal axis of its player.
+#[derive(Debug, Default)]
+struct HorizontalSimulation;
+
+impl SnakeSimulation for HorizontalSimulation {
+    fn tick(&mut self, game: &mut ExampleGame) {
+        let snake_count = game.snakes.snakeCount as usize;
+        for snake in &mut game.snakes.snakes[..snake_count] {
+            let Some(player) = game
+                .players
+                .players
+                .get(snake.controlledByPlayerIndex as usize)
+            else {
+                continue;
+            };
+            let input = player.playerInput;
+            if input.inputType == ExamplePlayerInputType::InGame {
+                snake.body[0].x += unsafe { input.input.inGameInput.horizontalAxis } as c_int;
+            }
+        }
+    }
+}
+
+fn move_left() -> ExamplePlayerInput {
+    ExamplePlayerInput {
+        inputType: ExamplePlayerInputType::InGame,
+        input: ExamplePlayerInputUnion {
+            inGameInput: ExamplePlayerInGameInput {
+                horizontalAxis: -1,
+                verticalAxis: 0,
+                abilityButton: Default::default(),
+            },
+        },
+        intentionalPadding: [0u8; 32],
+    }
+}
+
+#[test]
+fn custom_step_is_applied_to_authoritative_and_predicted() {
+    let participant_id = ParticipantId(1);
+    let mut game = ExampleGame::default();
+    game.participant_joined(participant_id.0);
+    let mut snake_game: SnakeGame = SnakeGame::new(game);
+
+    let mut step = AuthSnakeStep::new();
+    let _ = step.insert(
+        participant_id,
+        Step::Custom(SnakeStep { step: move_left() }),
+    );
+
+    AssentCallback::on_tick(&mut snake_game, &step);
+    let player_input = snake_game.authoritative().players.players[0].playerInput;
+    assert_eq!(player_input.inputType, ExamplePlayerInputType::InGame);
+    assert_eq!(unsafe { player_input.input.inGameInput.horizontalAxis }, -1);
+    assert_eq!(
+        snake_game.predicted().players.players[0]
+            .playerInput
+            .inputType,
+        ExamplePlayerInputType::Empty
+    );
+
+    SeerCallback::on_tick(&mut snake_game, &step);
+    assert_eq!(
+        snake_game.predicted().players.players[0]
+            .playerInput
+            .inputType,
+        ExamplePlayerInputType::InGame
+    );
+}
+
+#[test]
+fn tick_runs_simulation_after_applying_inputs() {
+    let participant_id = ParticipantId(1);
+    let mut game = ExampleGame::default();
+    game.participant_joined(participant_id.0);
+    let start_x = game.snakes.snakes[0].body[0].x;
+    let mut snake_game = SnakeGame::<HorizontalSimulation>::new(game);
+
+    let mut step = AuthSnakeStep::new();
+    let _ = step.insert(
+        participant_id,
+        Step::Custom(SnakeStep { step: move_left() }),
+    );
+    AssentCallback::on_tick(&mut snake_game, &step);
+    assert_eq!(
+        snake_game.authoritative().snakes.snakes[0].body[0].x,
+        start_x - 1
+    );
+    assert_eq!(snake_game.predicted().snakes.snakes[0].body[0].x, start_x);
+
+    let mut forced = AuthSnakeStep::new();
+    let _ = forced.insert(participant_id, Step::Forced);
+    AssentCallback::on_tick(&mut snake_game, &forced);
+    let player_input = snake_game.authoritative().players.players[0].playerInput;
+    assert_eq!(player_input.inputType, ExamplePlayerInputType::Empty);
+    assert_eq!(
+        snake_game.authoritative().snakes.snakes[0].body[0].x,
+        start_x - 1
+    );
+}
+
+#[test]
+fn example_simulation_steers_and_moves_snake() {
+    let mut game = ExampleGame::default();
+    game.participant_joined(1);
+    let start = game.snakes.snakes[0].body[0];
+
+    ExampleSimulation.tick(&mut game);
+    assert_eq!(game.snakes.snakes[0].body[0].y, start.y - 1);
+
+    game.set_participant_input(1, move_left());
+    ExampleSimulation.tick(&mut game);
+    let snake = game.snakes.snakes[0];
+    assert_eq!(snake.movementDirection, ExampleDirection::Left);
+    assert_eq!(snake.body[0].x, start.x - 1);
+    assert_eq!(snake.body[0].y, start.y - 1);
+}
+
+#[test]
+fn example_simulation_grows_snake_on_food_and_wraps() {
+    let mut game = ExampleGame::default();
+    game.area = ExampleGameArea {
+        width: 10,
+        height: 10,
+    };
+    game.participant_joined(1);
+    let snake = &mut game.snakes.snakes[0];
+    snake.body[0] = ExamplePosition { x: 0, y: 0 };
+    snake.movementDirection = ExampleDirection::Left;
+    game.food.position = ExamplePosition { x: 9, y: 0 };
+
+    ExampleSimulation.tick(&mut game);
+    let snake = game.snakes.snakes[0];
+    assert_eq!((snake.body[0].x, snake.body[0].y), (9, 0));
+    assert_eq!(snake.length, 2);
+    assert!(game.food.position.x != 9 || game.food.position.y != 0);
+
+    ExampleSimulation.tick(&mut game);
+    let snake = game.snakes.snakes[0];
+    assert_eq!((snake.body[0].x, snake.body[1].x), (8, 9));
+}
+
+#[test]
+fn joined_participant_gets_a_snake() {
+    let mut game = ExampleGame::default();
+    game.participant_joined(1);
+    game.participant_joined(2);
+    assert_eq!(game.lastParticipantLookupCount, 2);
+    assert_eq!(game.snakes.snakeCount, 2);
+    assert_eq!(game.players.players[1].snakeIndex, 1);
+    assert_eq!(game.snakes.snakes[1].controlledByPlayerIndex, 1);
+    assert_eq!(game.snakes.snakes[1].length, 1);
+
+    game.participant_left(1);
+    assert_eq!(game.lastParticipantLookupCount, 1);
+    assert_eq!(game.players.players[0].snakeIndex, EXAMPLE_ILLEGAL_INDEX);
+    assert_eq!(
+        game.snakes.snakes[0].controlledByPlayerIndex,
+        EXAMPLE_ILLEGAL_INDEX
+    );
+
+    game.participant_joined(3);
+    assert_eq!(game.snakes.snakeCount, 2);
+    assert_eq!(game.players.players[0].snakeIndex, 0);
+    assert_eq!(game.snakes.snakes[0].controlledByPlayerIndex, 0);
+}
+
+#[test]
+fn out_of_range_counts_and_indices_do_not_panic() {
+    let mut game = ExampleGame::default();
+    game.snakes.snakeCount = 200;
+    game.players.playerCount = 200;
+    game.participant_joined(1);
+
+    game.participantLookup[0].playerIndex = 9;
+    game.players.players[0].snakeIndex = 9;
+    game.set_participant_input(1, move_left());
+    game.participant_left(1);
+    game.participant_joined(2);
+}
+
+#[test]
+fn left_participant_releases_player() {
+    let mut game = ExampleGame::default();
+    game.participant_joined(1);
+    game.participant_joined(2);
+    assert_eq!(game.players.playerCount, 2);
+
+    game.participant_left(1);
+    game.participant_joined(3);
+    assert_eq!(game.players.playerCount, 2);
+    assert_eq!(game.participantLookup[0].participantId, 3);
+    assert_eq!(game.participantLookup[0].playerIndex, 0);
+}
+
+#[test]
+fn truncated_state_is_an_error() {
+    let truncated = vec![0u8; size_of::<ExampleGame>() - 1];
+    let result = <SnakeGame as BufferDeserializer>::deserialize(&truncated);
+    assert!(result.is_err());
+}
+
+#[test]
+fn out_of_range_enum_in_state_is_an_error() {
+    let mut octets = vec![0u8; size_of::<ExampleGame>()];
+    assert!(ExampleGame::try_from(octets.as_slice()).is_ok());
+
+    let direction_offset = offset_of!(ExampleGame, snakes)
+        + offset_of!(ExampleSnakes, snakes)
+        + offset_of!(ExampleSnake, movementDirection);
+    octets[direction_offset..direction_offset + 4].copy_from_slice(&7u32.to_ne_bytes());
+    assert_eq!(
+        ExampleGame::try_from(octets.as_slice()).unwrap_err(),
+        "Invalid ExampleDirection"
+    );
+
+    let mut octets = vec![0u8; size_of::<ExampleGame>()];
+    let input_type_offset = offset_of!(ExampleGame, players)
+        + offset_of!(ExamplePlayers, players)
+        + size_of::<ExamplePlayer>()
+        + offset_of!(ExamplePlayer, playerInput)
+        + offset_of!(ExamplePlayerInput, inputType);
+    octets[input_type_offset..input_type_offset + 4].copy_from_slice(&3u32.to_ne_bytes());
+    assert_eq!(
+        ExampleGame::try_from(octets.as_slice()).unwrap_err(),
+        "Invalid ExamplePlayerInputType"
+    );
+}
@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+//! [`proptest`] strategies for the types that go over the wire.
+//!
+//! The strategies only produce values that the encoders accept, so that every
+//! generated value is expected to survive an encode/decode round trip.
+
+use connection_layer::{ConnectionLayer, ConnectionLayerMode};
+use datagram_connections::{
+    ChallengeRequestId, ClientToHostChallengeCommand, ClientToHostCommands, ClientToHostPacket,
+    ConnectionId, DisconnectCommand, Nonce, ServerChallenge,
+};
+use proptest::prelude::*;
+
+/// Largest payload generated for packets. Keeps the cases fast while still
+/// covering payloads larger than a typical datagram.
+pub const MAX_PAYLOAD_SIZE: usize = 2048;
+
+pub fn nonce() -> impl Strategy<Value = Nonce> {
+    any::<u64>().prop_map(Nonce)
+}
+
+pub fn connection_id() -> impl Strategy<Value = ConnectionId> {
+    any::<u64>().prop_map(ConnectionId)
+}
+
+pub fn server_challenge() -> impl Strategy<Value = ServerChallenge> {
+    any::<u64>().prop_map(ServerChallenge)
+}
+
+pub fn challenge_request_id() -> impl Strategy<Value = ChallengeRequestId> {
+    any::<u64>().prop_map(ChallengeRequestId)
+}
+
+pub fn client_to_host_packet() -> impl Strategy<Value = ClientToHostPacket> {
+    (
+        connection_id(),
+        prop::collection::vec(any::<u8>(), 0..MAX_PAYLOAD_SIZE),
+    )
+        .prop_map(|(connection_id, payload)| {
+            ClientToHostPacket::new(connection_id, &payload)
+                .expect("generated payloads fit in the packet size")
+        })
+}
+
+/// Commands that [`ClientToHostCommands::from_stream`] can decode.
+pub fn client_to_host_command() -> impl Strategy<Value = ClientToHostCommands> {
+    prop_oneof![
+        (nonce(), challenge_request_id()).prop_map(|(nonce, request_id)| {
+            ClientToHostCommands::ChallengeType(ClientToHostChallengeCommand { nonce, request_id })
+        }),
+        connection_id().prop_map(|connection_id| ClientToHostCommands::DisconnectType(
+            DisconnectCommand { connection_id }
+        )),
+    ]
+}
+
+/// Encoded host to client commands, as the host would send them.
+///
+/// `HostToClientCommands` can only be built by decoding, so this produces the octets instead.
+pub fn host_to_client_command_octets() -> impl Strategy<Value = Vec<u8>> {
+    let challenge = (nonce(), challenge_request_id(), server_challenge()).prop_map(
+        |(nonce, request_id, challenge)| {
+            [
+                &[0x11][..],
+                &nonce.0.to_be_bytes(),
+                &request_id.0.to_be_bytes(),
+                &challenge.0.to_be_bytes(),
+            ]
+            .concat()
+        },
+    );
+    let connect = (nonce(), connection_id()).prop_map(|(nonce, connection_id)| {
+        [
+            &[0x12][..],
+            &nonce.0.to_be_bytes(),
+            &connection_id.0.to_be_bytes(),
+        ]
+        .concat()
+    });
+    let packet = (connection_id(), any::<u16>()).prop_map(|(connection_id, size)| {
+        [
+            &[0x13][..],
+            &connection_id.0.to_be_bytes(),
+            &size.to_be_bytes(),
+        ]
+        .concat()
+    });
+    let disconnect_ack = connection_id()
+        .prop_map(|connection_id| [&[0x14][..], &connection_id.0.to_be_bytes()].concat());
+    prop_oneof![challenge, connect, packet, disconnect_ack]
+}
+
+pub fn connection_layer_mode() -> impl Strategy<Value = ConnectionLayerMode> {
+    prop_oneof![
+        Just(()).prop_map(|()| ConnectionLayerMode::OOB),
+        (1..=u16::MAX, any::<u32>()).prop_map(|(value, murmur3_hash)| {
+            ConnectionLayerMode::Connection(ConnectionLayer {
+                connection_id: connection_layer::ConnectionId { value },
+                murmur3_hash,
+            })
+        }),
+    ]
+}
@@ -0,0 +1,105 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use connection_layer::prelude::{
+    ConnectionLayerClientCodec, ConnectionLayerHostCodec, ConnectionLayerMode,
+    ConnectionLayerParser, ConnectionSecretSeed, DatagramHostDecoder,
+};
+use datagram::{DatagramDecoder, DatagramParser};
+use datagram_connections::prelude::{Client, HostToClientCommands};
+use datagram_connections::{
+    ChallengeRequestId, ClientToHostCommands, ClientToHostPacket, ConnectionId, FromOctets, Nonce,
+    ServerChallenge, ToOctets,
+};
+use flood_rs::in_stream::InOctetStream;
+use flood_rs::out_stream::OutOctetStream;
+use proptest::prelude::*;
+use protocol_fuzz::*;
+use secure_random::SecureRandom;
+
+#[derive(Debug)]
+struct FakeRandom;
+
+impl SecureRandom for FakeRandom {
+    fn random_u64(&mut self) -> u64 {
+        1
+    }
+}
+
+fn random_octets() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), 0..64)
+}
+
+proptest! {
+    #[test]
+    fn value_types_round_trip(
+        nonce in nonce(),
+        connection_id in connection_id(),
+        server_challenge in server_challenge(),
+        challenge_request_id in challenge_request_id(),
+    ) {
+        prop_assert_eq!(Nonce::from_octets(&nonce.to_octets()?)?, nonce);
+        prop_assert_eq!(ConnectionId::from_octets(&connection_id.to_octets()?)?, connection_id);
+        prop_assert_eq!(
+            ServerChallenge::from_octets(&server_challenge.to_octets()?)?,
+            server_challenge
+        );
+        prop_assert_eq!(
+            ChallengeRequestId::from_octets(&challenge_request_id.to_octets()?)?,
+            challenge_request_id
+        );
+    }
+
+    #[test]
+    fn client_to_host_packet_round_trips(packet in client_to_host_packet()) {
+        let mut out_stream = OutOctetStream::new();
+        packet.to_stream(&mut out_stream)?;
+        let octets = out_stream.octets();
+
+        let mut in_stream = InOctetStream::new(&octets);
+        let decoded = ClientToHostPacket::from_stream(&mut in_stream)?;
+        prop_assert_eq!(decoded.header, packet.header);
+        prop_assert_eq!(decoded.payload, packet.payload);
+    }
+
+    #[test]
+    fn client_to_host_commands_round_trip(command in client_to_host_command()) {
+        let octets = command.to_octets()?;
+        prop_assert_eq!(ClientToHostCommands::from_octets(&octets)?.to_octets()?, octets);
+    }
+
+    #[test]
+    fn host_to_client_commands_round_trip(octets in host_to_client_command_octets()) {
+        prop_assert_eq!(HostToClientCommands::from_octets(&octets)?.to_octets()?, octets);
+    }
+
+    #[test]
+    fn connection_layer_mode_round_trips(mode in connection_layer_mode()) {
+        let mut out_stream = OutOctetStream::new();
+        mode.to_stream(&mut out_stream)?;
+        let octets = out_stream.octets();
+
+        let mut in_stream = InOctetStream::new(&octets);
+        prop_assert_eq!(ConnectionLayerMode::from_stream(&mut in_stream)?, mode);
+    }
+
+    /// Malformed datagrams must be reported as errors, never as panics.
+    #[test]
+    fn random_octets_never_panic(octets in random_octets()) {
+        let _ = HostToClientCommands::from_octets(&octets);
+        let _ = HostToClientCommands::try_from_stream(&mut InOctetStream::new(&octets));
+        let _ = ClientToHostCommands::from_octets(&octets);
+        let _ = ClientToHostPacket::from_stream(&mut InOctetStream::new(&octets));
+        let _ = ConnectionLayerMode::from_stream(&mut InOctetStream::new(&octets));
+
+        let _ = Client::new(Box::new(FakeRandom)).decode(&octets);
+        let _ = ConnectionLayerClientCodec::new(1).decode(&octets);
+        let _ = ConnectionLayerHostCodec::new(Box::new(FakeRandom)).decode(&octets);
+        let _ = ConnectionLayerParser::new(
+            connection_layer::ConnectionId { value: 1 },
+            ConnectionSecretSeed(0),
+        )
+        .parse(&octets);
+    }
+}
@@ -0,0 +1,88 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use crate::{check_sent_size, first_addr, same_family_addrs, wildcard_addr};
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use tokio::net::{lookup_host, ToSocketAddrs, UdpSocket};
+
+/// The async counterpart of [`UdpClient`](crate::UdpClient), built on [`tokio::net::UdpSocket`].
+///
+/// Connected and unconnected use follow the same rules as for `UdpClient`.
+pub struct AsyncUdpClient {
+    socket: UdpSocket,
+}
+
+impl AsyncUdpClient {
+    /// Connects to `host`, binding the wildcard address of the matching family.
+    ///
+    /// Resolved addresses are tried in order, like in [`UdpClient::new`](crate::UdpClient::new).
+    pub async fn new(host: &str) -> Result<Self> {
+        let mut last_error = None;
+        for host_addr in lookup_host(host).await? {
+            let socket = match UdpSocket::bind(wildcard_addr(&host_addr)).await {
+                Ok(socket) => socket,
+                Err(err) => {
+                    last_error = Some(err);
+                    continue;
+                }
+            };
+            match socket.connect(host_addr).await {
+                Ok(()) => return Ok(Self { socket }),
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("host '{host}' did not resolve"),
+            )
+        }))
+    }
+
+    /// Binds to the `local` address and connects to `host`.
+    ///
+    /// Fails with [`ErrorKind::InvalidInput`] if `host` does not resolve to an address
+    /// of the same family (IPv4/IPv6) as `local`.
+    pub async fn new_with_bind(local: &str, host: &str) -> Result<Self> {
+        let local_addr = first_addr(local, lookup_host(local).await?)?;
+        let host_addrs = same_family_addrs(local_addr, host, lookup_host(host).await?)?;
+
+        let socket = UdpSocket::bind(local_addr).await?;
+        socket.connect(host_addrs.as_slice()).await?;
+        Ok(Self { socket })
+    }
+
+    /// Binds to the `local` address without connecting to any host.
+    pub async fn bind(local: &str) -> Result<Self> {
+        let socket = UdpSocket::bind(local).await?;
+        Ok(Self { socket })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sends a datagram to the connected host.
+    pub async fn send(&self, data: &[u8]) -> Result<()> {
+        let size = self.socket.send(data).await?;
+        check_sent_size(size, data)
+    }
+
+    /// Waits for a datagram from the connected host.
+    pub async fn recv(&self, buffer: &mut [u8]) -> Result<usize> {
+        self.socket.recv(buffer).await
+    }
+
+    /// Sends a datagram to `addr`. Only valid on a socket created with [`AsyncUdpClient::bind`].
+    pub async fn send_to(&self, addr: impl ToSocketAddrs, data: &[u8]) -> Result<()> {
+        let size = self.socket.send_to(data, addr).await?;
+        check_sent_size(size, data)
+    }
+
+    /// Waits for a datagram from any peer. Only valid on a socket created with [`AsyncUdpClient::bind`].
+    pub async fn recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.socket.recv_from(buffer).await
+    }
+}
@@ -3,38 +3,158 @@
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
 use std::io::{Error, ErrorKind, Result};
-use std::net::UdpSocket;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
 
 use datagram::{DatagramReceiver, DatagramSender};
 
+#[cfg(feature = "async")]
+mod async_client;
+#[cfg(feature = "async")]
+pub use async_client::AsyncUdpClient;
+
+/// A nonblocking UDP socket, either connected to a single host or unconnected.
+///
+/// A client created with [`UdpClient::new`] is connected and should use
+/// [`DatagramSender::send`] / [`DatagramReceiver::receive`]. A client created
+/// with [`UdpClient::bind`] is unconnected and should use [`UdpClient::send_to`]
+/// / [`UdpClient::recv_from`]. Mixing connected and unconnected sends on the
+/// same socket is an error: a connected socket only talks to its peer, and an
+/// unconnected socket has no peer for `send` to use.
 pub struct UdpClient {
     socket: UdpSocket,
 }
 
 impl UdpClient {
+    /// Connects to `host`, binding the wildcard address of the matching family.
+    ///
+    /// If `host` resolves to several addresses, they are tried in order and the first
+    /// one that can be connected to is used, so both IPv4 and IPv6 hosts work.
     pub fn new(host: &str) -> Result<Self> {
-        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        let mut last_error = None;
+        for host_addr in host.to_socket_addrs()? {
+            let socket = UdpSocket::bind(wildcard_addr(&host_addr))
+                .and_then(|socket| socket.connect(host_addr).map(|()| socket));
+            match socket {
+                Ok(socket) => {
+                    socket.set_nonblocking(true)?;
+                    return Ok(UdpClient { socket });
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("host '{host}' did not resolve"),
+            )
+        }))
+    }
+
+    /// Binds to the `local` address and connects to `host`.
+    ///
+    /// Use this instead of [`UdpClient::new`] to pin the source port or interface.
+    /// Fails with [`ErrorKind::InvalidInput`] if `host` does not resolve to an address
+    /// of the same family (IPv4/IPv6) as `local`.
+    pub fn new_with_bind(local: &str, host: &str) -> Result<Self> {
+        let local_addr = first_addr(local, local.to_socket_addrs()?)?;
+        let host_addrs = same_family_addrs(local_addr, host, host.to_socket_addrs()?)?;
+
+        let socket = UdpSocket::bind(local_addr)?;
+        socket.set_nonblocking(true)?;
+        socket.connect(host_addrs.as_slice())?;
+        Ok(UdpClient { socket })
+    }
+
+    /// Binds to the `local` address without connecting to any host.
+    pub fn bind(local: &str) -> Result<Self> {
+        let socket = UdpSocket::bind(local)?;
         socket.set_nonblocking(true)?;
-        socket.connect(host)?;
         Ok(UdpClient { socket })
     }
 
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    #[deprecated(note = "use `DatagramSender::send` instead")]
     pub fn send_datagram(&self, data: &[u8]) -> Result<()> {
         let size = self.socket.send(data)?;
-        if size != data.len() {
-            return Err(Error::new(
-                ErrorKind::WriteZero,
-                "failed to send the entire datagram",
-            ));
-        }
-        Ok(())
+        check_sent_size(size, data)
     }
+
+    #[deprecated(note = "use `DatagramReceiver::receive` instead")]
+    pub fn receive_datagram(&self, buffer: &mut [u8]) -> Result<usize> {
+        self.socket.recv(buffer)
+    }
+
+    /// Sends a datagram to `addr`. Only valid on a socket created with [`UdpClient::bind`].
+    pub fn send_to(&self, addr: impl ToSocketAddrs, data: &[u8]) -> Result<()> {
+        let size = self.socket.send_to(data, addr)?;
+        check_sent_size(size, data)
+    }
+
+    /// Receives a datagram from any peer. Only valid on a socket created with [`UdpClient::bind`].
+    pub fn recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.socket.recv_from(buffer)
+    }
+}
+
+/// Fails with [`ErrorKind::WriteZero`] unless all of `data` was sent.
+fn check_sent_size(size: usize, data: &[u8]) -> Result<()> {
+    if size != data.len() {
+        return Err(Error::new(
+            ErrorKind::WriteZero,
+            "failed to send the entire datagram",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the unspecified address, with any port, in the same family as `addr`.
+fn wildcard_addr(addr: &SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+/// Returns the first address `name` resolved to.
+fn first_addr(name: &str, mut addrs: impl Iterator<Item = SocketAddr>) -> Result<SocketAddr> {
+    addrs.next().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("local address '{name}' did not resolve"),
+        )
+    })
+}
+
+/// Keeps the addresses `host` resolved to that are in the same family (IPv4/IPv6) as `local_addr`.
+fn same_family_addrs(
+    local_addr: SocketAddr,
+    host: &str,
+    addrs: impl Iterator<Item = SocketAddr>,
+) -> Result<Vec<SocketAddr>> {
+    let host_addrs: Vec<SocketAddr> = addrs
+        .filter(|addr| addr.is_ipv4() == local_addr.is_ipv4())
+        .collect();
+    if host_addrs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "host '{host}' has no address in the same family as local address {local_addr}"
+            ),
+        ));
+    }
+    Ok(host_addrs)
 }
 
 impl DatagramSender for UdpClient {
+    /// Sends a datagram to the connected host.
+    ///
+    /// Fails with [`ErrorKind::WriteZero`] if the socket did not send the whole datagram.
     fn send(&mut self, data: &[u8]) -> Result<()> {
-        self.socket.send(data)?;
-        Ok(())
+        let size = self.socket.send(data)?;
+        check_sent_size(size, data)
     }
 }
 
@@ -2,10 +2,153 @@
  * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/workspace
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
+use datagram::{DatagramReceiver, DatagramSender};
 use udp_client::UdpClient;
 
 #[test_log::test]
 fn it_works() {
-    let client = UdpClient::new("localhost:23000").unwrap();
-    client.send_datagram(&[0x18, 0x28]).unwrap();
+    let mut client = UdpClient::new("localhost:23000").unwrap();
+    client.send(&[0x18, 0x28]).unwrap();
+}
+
+#[test_log::test]
+#[allow(deprecated)]
+fn old_and_new_method_names() {
+    let host = UdpClient::bind("127.0.0.1:0").unwrap();
+    let host_addr = host.local_addr().unwrap();
+    let mut client = UdpClient::new(&host_addr.to_string()).unwrap();
+
+    client.send_datagram(&[0x01]).unwrap();
+    client.send(&[0x02]).unwrap();
+
+    let mut buf = [0u8; 1200];
+    for expected in [0x01, 0x02] {
+        let (size, from) = receive_blocking(&host, &mut buf);
+        assert_eq!(&buf[..size], &[expected]);
+        host.send_to(from, &buf[..size]).unwrap();
+    }
+
+    let mut received = Vec::new();
+    while received.len() < 2 {
+        let result = if received.is_empty() {
+            client.receive_datagram(&mut buf)
+        } else {
+            client.receive(&mut buf)
+        };
+        match result {
+            Ok(size) => received.push(buf[..size].to_vec()),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(std::time::Duration::from_millis(1))
+            }
+            Err(e) => panic!("receive failed: {e}"),
+        }
+    }
+    assert_eq!(received, vec![vec![0x01], vec![0x02]]);
+}
+
+fn receive_blocking(client: &UdpClient, buf: &mut [u8]) -> (usize, std::net::SocketAddr) {
+    loop {
+        match client.recv_from(buf) {
+            Ok(received) => return received,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(std::time::Duration::from_millis(1))
+            }
+            Err(e) => panic!("recv_from failed: {e}"),
+        }
+    }
+}
+
+#[test_log::test]
+fn unconnected_send_to_and_recv_from() {
+    let first = UdpClient::bind("127.0.0.1:0").unwrap();
+    let second = UdpClient::bind("127.0.0.1:0").unwrap();
+    let second_addr = second.local_addr().unwrap();
+
+    first.send_to(second_addr, &[0x18, 0x28]).unwrap();
+
+    let mut buf = [0u8; 1200];
+    let (size, from) = receive_blocking(&second, &mut buf);
+
+    assert_eq!(&buf[..size], &[0x18, 0x28]);
+    assert_eq!(from, first.local_addr().unwrap());
+}
+
+#[test_log::test]
+fn new_with_bind_uses_local_address() {
+    let host = UdpClient::bind("127.0.0.1:0").unwrap();
+    let host_addr = host.local_addr().unwrap();
+    let mut client = UdpClient::new_with_bind("127.0.0.1:0", &host_addr.to_string()).unwrap();
+    let client_addr = client.local_addr().unwrap();
+    assert!(client_addr.ip().is_loopback());
+
+    client.send(&[0x42]).unwrap();
+
+    let mut buf = [0u8; 1200];
+    let (size, from) = receive_blocking(&host, &mut buf);
+    assert_eq!(&buf[..size], &[0x42]);
+    assert_eq!(from, client_addr);
+}
+
+#[test_log::test]
+fn new_with_bind_rejects_mismatched_address_family() {
+    let result = UdpClient::new_with_bind("127.0.0.1:0", "[::1]:23000");
+    let err = result
+        .err()
+        .expect("mismatched address families should fail");
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_exchange_over_loopback() {
+    use udp_client::AsyncUdpClient;
+
+    let host = AsyncUdpClient::bind("127.0.0.1:0").await.unwrap();
+    let host_addr = host.local_addr().unwrap();
+    let client = AsyncUdpClient::new_with_bind("127.0.0.1:0", &host_addr.to_string())
+        .await
+        .unwrap();
+
+    client.send(&[0x18, 0x28]).await.unwrap();
+
+    let mut buf = [0u8; 1200];
+    let (size, from) = host.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..size], &[0x18, 0x28]);
+    assert_eq!(from, client.local_addr().unwrap());
+
+    host.send_to(from, &[0x38]).await.unwrap();
+    let size = client.recv(&mut buf).await.unwrap();
+    assert_eq!(&buf[..size], &[0x38]);
+}
+
+#[test_log::test]
+fn large_datagram_is_sent_in_full() {
+    let host = UdpClient::bind("127.0.0.1:0").unwrap();
+    let host_addr = host.local_addr().unwrap();
+    let mut client = UdpClient::new_with_bind("127.0.0.1:0", &host_addr.to_string()).unwrap();
+
+    let datagram: Vec<u8> = (0..8000).map(|i| i as u8).collect();
+    client.send(&datagram).unwrap();
+
+    let mut buf = [0u8; 9000];
+    let (size, _) = receive_blocking(&host, &mut buf);
+    assert_eq!(&buf[..size], datagram.as_slice());
+}
+
+#[test_log::test]
+fn connects_over_ipv6_loopback() {
+    let Ok(host) = UdpClient::bind("[::1]:0") else {
+        eprintln!("IPv6 is not available, skipping");
+        return;
+    };
+    let host_addr = host.local_addr().unwrap();
+    let mut client = UdpClient::new(&host_addr.to_string()).unwrap();
+    assert!(client.local_addr().unwrap().is_ipv6());
+
+    client.send(&[0x18, 0x28]).unwrap();
+
+    let mut buf = [0u8; 1200];
+    let (size, from) = receive_blocking(&host, &mut buf);
+    assert_eq!(&buf[..size], &[0x18, 0x28]);
+    assert_eq!(from.port(), client.local_addr().unwrap().port());
 }